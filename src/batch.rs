@@ -0,0 +1,313 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Error, Result};
+
+use crate::{
+    subtitle::{
+        discovery::{discover_subtitles, DiscoveredSubtitle},
+        format::SubtitleFormat,
+        language::Language,
+        matching::match_subtitles,
+    },
+    utils::canonicalize_file,
+    video::format::VideoFormat,
+    MuxOptions,
+};
+
+/// How processing a video of a batch ended.
+pub enum BatchStatus {
+    /// The subtitles were added to the video.
+    Done,
+
+    /// The video was left untouched for the given reason.
+    Skipped(Box<str>),
+
+    /// Processing the video failed with the given error.
+    Failed(Error),
+}
+
+pub struct BatchResult {
+    pub file_name: Box<str>,
+    pub status: BatchStatus,
+}
+
+impl BatchResult {
+    pub fn is_failed(&self) -> bool {
+        matches!(self.status, BatchStatus::Failed(_))
+    }
+}
+
+/// The subtitles paired with a video of a batch.
+pub struct BatchJob {
+    pub video_file: Box<str>,
+    pub subtitles: Vec<DiscoveredSubtitle>,
+
+    /// Why the subtitles next to the video couldn't be looked for, failing the video.
+    pub error: Option<Error>,
+}
+
+fn visit_directory<F>(directory: &Path, is_wanted: &F, files: &mut Vec<Box<str>>) -> Result<()>
+where
+    F: Fn(&Path) -> bool,
+{
+    let entries = fs::read_dir(directory).map_err(|e| {
+        anyhow!(
+            "Failed to read the directory {}: {}.",
+            directory.display(),
+            e
+        )
+    })?;
+    for entry in entries {
+        let file = entry?.path();
+
+        // Hidden files include the temporary outputs of in place modifications
+        let is_hidden = file
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if is_hidden {
+            continue;
+        }
+        if file.is_dir() {
+            visit_directory(&file, is_wanted, files)?;
+        } else if is_wanted(&file) {
+            let file_name = file.to_str().ok_or_else(|| {
+                anyhow!(
+                    "The file {} is not valid UTF-8. Please rename the file.",
+                    file.display()
+                )
+            })?;
+            files.push(file_name.into());
+        }
+    }
+    Ok(())
+}
+
+fn find_files<S, F>(directory: S, is_wanted: F) -> Result<Vec<Box<str>>>
+where
+    S: AsRef<str>,
+    F: Fn(&Path) -> bool,
+{
+    let directory = Path::new(directory.as_ref());
+    if !directory.is_dir() {
+        return Err(anyhow!(
+            "{} is not a directory. Please select a valid directory.",
+            directory.display()
+        ));
+    }
+
+    let mut files = Vec::new();
+    visit_directory(directory, &is_wanted, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+pub fn find_video_files<S>(directory: S) -> Result<Vec<Box<str>>>
+where
+    S: AsRef<str>,
+{
+    find_files(directory, |file| VideoFormat::new(file).is_ok())
+}
+
+pub fn find_subtitle_files<S>(directory: S) -> Result<Vec<Box<str>>>
+where
+    S: AsRef<str>,
+{
+    find_files(directory, |file| SubtitleFormat::new(file).is_ok())
+}
+
+/// Pairs every video of a directory tree with its subtitles, preferring the ones named
+/// after the video and matching the rest by episode and name similarity. The videos named
+/// like the output of another one, left by an earlier run, are skipped.
+pub fn plan_batch_jobs<S>(directory: S, options: &MuxOptions) -> Result<Vec<BatchJob>>
+where
+    S: AsRef<str>,
+{
+    let video_files = find_video_files(&directory)?;
+    let mut jobs = Vec::with_capacity(video_files.len());
+    for video_file in video_files.into_iter() {
+        // Only this video fails when its directory can't be read
+        let (subtitles, error) = match discover_subtitles(Path::new(video_file.as_ref())) {
+            Ok(subtitles) => (subtitles, None),
+            Err(e) => (Vec::new(), Some(e)),
+        };
+        jobs.push(BatchJob {
+            video_file,
+            subtitles,
+            error,
+        });
+    }
+    if !options.in_place {
+        let result_files = get_result_files(&jobs, options);
+        jobs.retain(|job| {
+            canonicalize_file(Path::new(job.video_file.as_ref()))
+                .is_none_or(|video_file| !result_files.contains(&video_file))
+        });
+    }
+
+    let paired_subtitle_files: HashSet<Box<str>> = jobs
+        .iter()
+        .flat_map(|job| job.subtitles.iter())
+        .map(|subtitle| subtitle.file_name.clone())
+        .collect();
+    let unpaired_subtitle_files: Vec<Box<str>> = find_subtitle_files(&directory)?
+        .into_iter()
+        .filter(|file_name| !paired_subtitle_files.contains(file_name))
+        .collect();
+    let video_files: Vec<&str> = jobs.iter().map(|job| job.video_file.as_ref()).collect();
+    let matches = match_subtitles(&video_files, &unpaired_subtitle_files);
+    // The subtitles named after the video win over the matched ones
+    for (job, subtitles) in jobs.iter_mut().zip(matches) {
+        for subtitle in subtitles.into_iter() {
+            if !job
+                .subtitles
+                .iter()
+                .any(|other| other.is_same_track(&subtitle))
+            {
+                job.subtitles.push(subtitle);
+            }
+        }
+    }
+    Ok(jobs)
+}
+
+fn get_result_files(jobs: &[BatchJob], options: &MuxOptions) -> HashSet<PathBuf> {
+    let template = options.get_output_template();
+    jobs.iter()
+        .filter_map(|job| {
+            let languages: Vec<Language> = job
+                .subtitles
+                .iter()
+                .filter_map(|subtitle| subtitle.language.clone())
+                .collect();
+            let result_file = template
+                .render(
+                    &job.video_file,
+                    options.output_directory.as_ref(),
+                    &languages,
+                )
+                .ok()?;
+            canonicalize_file(Path::new(result_file.as_ref()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::video::naming::OutputTemplate;
+
+    #[test]
+    fn test_find_video_files_recursively() -> Result<()> {
+        let directory = TempDir::new()?;
+        let season = directory.path().join("Season 1");
+        fs::create_dir(&season)?;
+        for file_name in ["e02.mkv", "e01.mp4", "e01.srt", ".e01.subtrack-1.mp4"] {
+            fs::write(season.join(file_name), b"")?;
+        }
+        fs::write(directory.path().join("notes.txt"), b"")?;
+
+        let video_files = find_video_files(directory.path().to_str().unwrap())?;
+        let expected: Vec<Box<str>> = ["e01.mp4", "e02.mkv"]
+            .iter()
+            .map(|file_name| season.join(file_name).to_str().unwrap().into())
+            .collect();
+        assert_eq!(video_files, expected);
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_find_video_files_in_missing_directory() {
+        find_video_files("my non existent directory").unwrap();
+    }
+
+    #[test]
+    fn test_plan_batch_jobs() -> Result<()> {
+        let directory = TempDir::new()?;
+        let subs = directory.path().join("subtitles");
+        fs::create_dir(&subs)?;
+        for file_name in [
+            "Show.S01E01.mkv",
+            "Show.S01E01.en.srt",
+            "Show.S01E02.mkv",
+            "subtitles/Show 1x01.fr.srt",
+            "subtitles/Show 1x02.fr.srt",
+        ] {
+            fs::write(directory.path().join(file_name), b"")?;
+        }
+
+        let jobs = plan_batch_jobs(directory.path().to_str().unwrap(), &MuxOptions::default())?;
+        let subtitle_names: Vec<Vec<String>> = jobs
+            .iter()
+            .map(|job| {
+                job.subtitles
+                    .iter()
+                    .map(|subtitle| {
+                        Path::new(subtitle.file_name.as_ref())
+                            .file_name()
+                            .unwrap()
+                            .to_string_lossy()
+                            .into_owned()
+                    })
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            subtitle_names,
+            vec![
+                vec!["Show.S01E01.en.srt", "Show 1x01.fr.srt"],
+                vec!["Show 1x02.fr.srt"]
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_batch_jobs_skips_earlier_outputs() -> Result<()> {
+        let directory = TempDir::new()?;
+        for file_name in [
+            "Show.S01E01.mkv",
+            "Show.S01E01-subs.mkv",
+            "Show.S01E01.en.srt",
+            "Show.S01E02.mkv",
+            "Show.S01E02.en.mkv",
+            "Show.S01E02.en.srt",
+        ] {
+            fs::write(directory.path().join(file_name), b"")?;
+        }
+        let video_names = |jobs: Vec<BatchJob>| -> Vec<String> {
+            jobs.iter()
+                .map(|job| {
+                    Path::new(job.video_file.as_ref())
+                        .file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect()
+        };
+
+        let jobs = plan_batch_jobs(directory.path().to_str().unwrap(), &MuxOptions::default())?;
+        assert_eq!(
+            video_names(jobs),
+            vec!["Show.S01E01.mkv", "Show.S01E02.en.mkv", "Show.S01E02.mkv"]
+        );
+
+        let options = MuxOptions {
+            output_template: Some("{stem}.{langs}.{ext}".parse::<OutputTemplate>()?),
+            ..MuxOptions::default()
+        };
+        let jobs = plan_batch_jobs(directory.path().to_str().unwrap(), &options)?;
+        assert_eq!(
+            video_names(jobs),
+            vec!["Show.S01E01-subs.mkv", "Show.S01E01.mkv", "Show.S01E02.mkv"]
+        );
+        Ok(())
+    }
+}
@@ -1,9 +1,20 @@
+use anyhow::{anyhow, Result};
 use clap::ValueEnum;
 use std::fmt;
 
-use crate::subtitle::file::SubtitleFile;
+use crate::{
+    subtitle::{
+        file::SubtitleFile,
+        order::{SubtitleOrder, SubtitleTrack},
+        selector::{select_subtitle_streams, SubtitleSelector},
+    },
+    video::{
+        format::VideoFormat,
+        stream::{MediaInfo, StreamInfo},
+    },
+};
 
-#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum Behavior {
     /// Keep all pre-existing tracks and add the new ones.
     #[default]
@@ -11,6 +22,12 @@ pub enum Behavior {
 
     /// Remove all pre-existing tracks and add the new ones.
     Overwrite,
+
+    /// Remove the pre-existing tracks selected with --remove-subtitle and add the new ones.
+    Remove,
+
+    /// Replace the pre-existing tracks that share a language with the new ones.
+    Replace,
 }
 
 impl fmt::Display for Behavior {
@@ -18,43 +35,331 @@ impl fmt::Display for Behavior {
         match self {
             Behavior::Append => write!(f, "append"),
             Behavior::Overwrite => write!(f, "overwrite"),
+            Behavior::Remove => write!(f, "remove"),
+            Behavior::Replace => write!(f, "replace"),
         }
     }
 }
 
-fn add_subtitles_to_video<S>(args: &mut Vec<String>, subtitles: S) -> ()
+fn add_subtitle_metadata(
+    args: &mut Vec<String>,
+    sub: &SubtitleFile,
+    output_index: usize,
+    output_format: &VideoFormat,
+) {
+    let metadata_option = format!("-metadata:s:s:{}", output_index);
+    if let Some(language) = &sub.language {
+        args.extend([
+            metadata_option.clone(),
+            format!("language={}", language.to_metadata_tag()),
+        ]);
+    }
+    if let Some(title) = &sub.title {
+        args.extend([metadata_option.clone(), format!("title={}", title)]);
+
+        // MP4 players read the track name from the handler instead
+        if *output_format == VideoFormat::MP4 {
+            args.extend([metadata_option, format!("handler_name={}", title)]);
+        }
+    }
+}
+
+fn get_streams_replaced_by<S>(subtitles: S, media_info: &MediaInfo) -> Vec<&StreamInfo>
+where
+    S: AsRef<[SubtitleFile]>,
+{
+    let selectors: Vec<SubtitleSelector> = subtitles
+        .as_ref()
+        .iter()
+        .filter_map(|sub| sub.language.as_ref())
+        .map(|language| SubtitleSelector::Language(language.to_metadata_tag()))
+        .collect();
+    media_info
+        .subtitle_streams()
+        .filter(|stream| selectors.iter().any(|selector| selector.matches(stream)))
+        .collect()
+}
+
+/// Builds the metadata and disposition arguments for the subtitle tracks of the output.
+pub fn get_args_for_adding_subtitles<S>(
+    subtitles: S,
+    tracks: &[SubtitleTrack],
+    output_format: &VideoFormat,
+) -> Result<Vec<String>>
 where
     S: AsRef<[SubtitleFile]>,
 {
-    for (i, sub) in subtitles.as_ref().iter().enumerate() {
-        if let Some(language) = &sub.language {
-            args.extend([
-                format!("-metadata:s:s:{}", i).into(),
-                format!("language={}", language.to_metadata_tag()).into(),
-            ]);
+    let subtitles = subtitles.as_ref();
+    let default_subtitles = subtitles
+        .iter()
+        .filter(|sub| sub.disposition.default)
+        .count();
+    if default_subtitles > 1 {
+        return Err(anyhow!("Only one subtitle can be marked as default."));
+    }
+
+    let mut args = Vec::new();
+    for (output_index, track) in tracks.iter().enumerate() {
+        match track {
+            SubtitleTrack::New(i) => {
+                let sub = &subtitles[*i];
+                add_subtitle_metadata(&mut args, sub, output_index, output_format);
+                if !sub.disposition.is_empty() {
+                    args.extend([
+                        format!("-disposition:s:{}", output_index),
+                        sub.disposition.to_ffmpeg_value().into(),
+                    ]);
+                }
+            }
+
+            // Only one track can be picked by default, so clear it from the kept ones
+            SubtitleTrack::Existing(stream) => {
+                if default_subtitles > 0 && stream.default {
+                    args.extend([
+                        format!("-disposition:s:{}", output_index),
+                        "-default".into(),
+                    ]);
+                }
+            }
         }
     }
+    Ok(args)
 }
 
 impl Behavior {
-    pub fn get_args_for_adding_subtitles<S>(&self, subtitles: S) -> Vec<String>
+    /// Decides which subtitle tracks end up in the output video and in which order.
+    pub fn get_subtitle_tracks<'a, S>(
+        &self,
+        subtitles: S,
+        media_info: &'a MediaInfo,
+        selectors: &[SubtitleSelector],
+        order: Option<&SubtitleOrder>,
+    ) -> Result<Vec<SubtitleTrack<'a>>>
     where
         S: AsRef<[SubtitleFile]>,
     {
-        let mut args = Vec::new();
-        match self {
-            // Don't negatively map any subtitles
-            Behavior::Append => {
-                args.extend(["-map".into(), "0:s?".into()]);
-                add_subtitles_to_video(&mut args, subtitles);
-                args
-            }
+        // Pre-existing subtitle tracks to remove, where None removes all of them,
+        // and the order used when the user doesn't choose one
+        let (removed_streams, default_order) = match self {
+            // Don't remove any subtitles
+            Behavior::Append => (Some(Vec::new()), SubtitleOrder::NewFirst),
 
-            // Negative map all subtitles tracks from the original
-            Behavior::Overwrite => {
-                add_subtitles_to_video(&mut args, subtitles);
-                args
+            // Remove all subtitles tracks from the original
+            Behavior::Overwrite => (None, SubtitleOrder::NewFirst),
+
+            // Remove only the selected subtitle tracks from the original
+            Behavior::Remove => {
+                if selectors.is_empty() {
+                    return Err(anyhow!(
+                        "At least one subtitle must be selected for removal with the remove behavior."
+                    ));
+                }
+                (
+                    Some(select_subtitle_streams(media_info, selectors)?),
+                    SubtitleOrder::NewFirst,
+                )
             }
+
+            // Remove the subtitle tracks sharing a language with the new ones,
+            // which are placed after the kept tracks
+            Behavior::Replace => (
+                Some(get_streams_replaced_by(&subtitles, media_info)),
+                SubtitleOrder::ExistingFirst,
+            ),
+        };
+
+        let kept_streams: Vec<&StreamInfo> = match &removed_streams {
+            None => Vec::new(),
+            Some(removed_streams) => media_info
+                .subtitle_streams()
+                .filter(|stream| !removed_streams.iter().any(|s| s.index == stream.index))
+                .collect(),
+        };
+        order
+            .unwrap_or(&default_order)
+            .arrange(subtitles.as_ref(), &kept_streams)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        subtitle::{
+            disposition::Disposition, format::SubtitleFormat, handling::SubtitleHandling,
+            language::Language,
+        },
+        video::stream::StreamKind,
+    };
+
+    fn get_subtitles() -> Vec<SubtitleFile> {
+        vec![SubtitleFile {
+            language: Some(Language::Spanish),
+            disposition: Disposition::default(),
+            title: None,
+            format: SubtitleFormat::Srt,
+            file_name: "subtitle.srt".into(),
+            mode: SubtitleHandling::Keep,
+            is_original_subtitle_file: true,
+            is_written: true,
+        }]
+    }
+
+    fn get_media_info() -> MediaInfo {
+        let subtitle = |index, language: &str| StreamInfo {
+            index,
+            kind: StreamKind::Subtitle,
+            codec: Some("subrip".into()),
+            language: Some(language.into()),
+            default: index == 0,
+            ..Default::default()
+        };
+        MediaInfo {
+            streams: vec![subtitle(0, "eng"), subtitle(1, "spa"), subtitle(2, "fre")],
+            duration: None,
+        }
+    }
+
+    fn get_track_names(tracks: &[SubtitleTrack]) -> Vec<String> {
+        tracks
+            .iter()
+            .map(|track| match track {
+                SubtitleTrack::New(i) => format!("new:{}", i + 1),
+                SubtitleTrack::Existing(stream) => stream.index.to_string(),
+            })
+            .collect()
+    }
+
+    fn get_args(
+        behavior: Behavior,
+        subtitles: Vec<SubtitleFile>,
+        selectors: &[SubtitleSelector],
+        output_format: &VideoFormat,
+    ) -> Result<Vec<String>> {
+        let media_info = get_media_info();
+        let tracks = behavior.get_subtitle_tracks(&subtitles, &media_info, selectors, None)?;
+        get_args_for_adding_subtitles(&subtitles, &tracks, output_format)
+    }
+
+    #[test]
+    fn test_append_keeps_existing_subtitles() -> Result<()> {
+        let media_info = get_media_info();
+        let tracks =
+            Behavior::Append.get_subtitle_tracks(get_subtitles(), &media_info, &[], None)?;
+        assert_eq!(get_track_names(&tracks), ["new:1", "0", "1", "2"]);
+        assert_eq!(
+            get_args(Behavior::Append, get_subtitles(), &[], &VideoFormat::MKV)?,
+            ["-metadata:s:s:0", "language=spa"].map(String::from)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_overwrite_removes_existing_subtitles() -> Result<()> {
+        let media_info = get_media_info();
+        let tracks =
+            Behavior::Overwrite.get_subtitle_tracks(get_subtitles(), &media_info, &[], None)?;
+        assert_eq!(get_track_names(&tracks), ["new:1"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_drops_selected_subtitles() -> Result<()> {
+        let media_info = get_media_info();
+        let selectors = [
+            SubtitleSelector::Language("eng".into()),
+            SubtitleSelector::Index(2),
+        ];
+        let tracks =
+            Behavior::Remove.get_subtitle_tracks(get_subtitles(), &media_info, &selectors, None)?;
+        assert_eq!(get_track_names(&tracks), ["new:1", "1"]);
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_remove_requires_selectors() {
+        Behavior::Remove
+            .get_subtitle_tracks(get_subtitles(), &get_media_info(), &[], None)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_replace_swaps_subtitles_with_same_language() -> Result<()> {
+        let media_info = get_media_info();
+        let tracks =
+            Behavior::Replace.get_subtitle_tracks(get_subtitles(), &media_info, &[], None)?;
+        assert_eq!(get_track_names(&tracks), ["0", "2", "new:1"]);
+        assert_eq!(
+            get_args(Behavior::Replace, get_subtitles(), &[], &VideoFormat::MKV)?,
+            ["-metadata:s:s:2", "language=spa"].map(String::from)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_order_overrides_behavior_order() -> Result<()> {
+        let media_info = get_media_info();
+        let tracks = Behavior::Append.get_subtitle_tracks(
+            get_subtitles(),
+            &media_info,
+            &[],
+            Some(&SubtitleOrder::ExistingFirst),
+        )?;
+        assert_eq!(get_track_names(&tracks), ["0", "1", "2", "new:1"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_default_subtitle_clears_existing_default() -> Result<()> {
+        let mut subtitles = get_subtitles();
+        subtitles[0].disposition = Disposition {
+            default: true,
+            forced: true,
+            hearing_impaired: false,
+        };
+        let args = get_args(Behavior::Append, subtitles, &[], &VideoFormat::MKV)?;
+        assert_eq!(
+            args[2..],
+            [
+                "-disposition:s:0",
+                "default+forced",
+                "-disposition:s:1",
+                "-default"
+            ]
+            .map(String::from)
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_only_one_default_subtitle() {
+        let mut subtitles = get_subtitles();
+        subtitles.extend(get_subtitles());
+        for subtitle in subtitles.iter_mut() {
+            subtitle.disposition.default = true;
         }
+        get_args(Behavior::Append, subtitles, &[], &VideoFormat::MKV).unwrap();
+    }
+
+    #[test]
+    fn test_titles_for_mp4_also_set_handler_name() -> Result<()> {
+        let mut subtitles = get_subtitles();
+        subtitles[0].title = Some("Spanish SDH".into());
+        let args = get_args(Behavior::Overwrite, subtitles, &[], &VideoFormat::MP4)?;
+        assert_eq!(
+            args,
+            [
+                "-metadata:s:s:0",
+                "language=spa",
+                "-metadata:s:s:0",
+                "title=Spanish SDH",
+                "-metadata:s:s:0",
+                "handler_name=Spanish SDH"
+            ]
+            .map(String::from)
+        );
+        Ok(())
     }
 }
@@ -0,0 +1,591 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use clap::{parser::ValueSource, value_parser, Arg, ArgAction, ArgMatches, Command};
+use toml::{Table, Value};
+
+use crate::subtitle::{
+    encoding::get_encoder,
+    language::{Language, LanguageEncoders},
+};
+
+/// The name of the project configuration, looked up from the current directory to the root.
+pub const LOCAL_CONFIG_FILE_NAME: &str = ".subtrack.toml";
+
+#[derive(Clone, Debug, PartialEq)]
+struct OptionDefault {
+    values: Vec<String>,
+    file_name: Box<str>,
+}
+
+/// The option defaults and encoders of the top of a configuration or of a profile.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigSettings {
+    options: BTreeMap<Box<str>, OptionDefault>,
+    pub language_encoders: LanguageEncoders,
+}
+
+/// The settings read from the configuration files, the project one overriding the user one.
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    settings: ConfigSettings,
+    default_profile: Option<Box<str>>,
+    profiles: BTreeMap<Box<str>, ConfigSettings>,
+}
+
+fn get_option_values(key: &str, value: &Value, file_name: &str) -> Result<Vec<String>> {
+    let invalid_value = || {
+        anyhow!(
+            "The option {} in {} must be a string, a number, a boolean or a list of them.",
+            key,
+            file_name
+        )
+    };
+    let get_value = |value: &Value| match value {
+        Value::String(value) => Ok(value.clone()),
+        Value::Integer(value) => Ok(value.to_string()),
+        Value::Float(value) => Ok(value.to_string()),
+        Value::Boolean(value) => Ok(value.to_string()),
+        _ => Err(invalid_value()),
+    };
+    match value {
+        Value::Array(values) => values.iter().map(get_value).collect(),
+        value => Ok(vec![get_value(value)?]),
+    }
+}
+
+fn get_language_encoders<S>(table: &Table, file_name: S) -> Result<LanguageEncoders>
+where
+    S: AsRef<str>,
+{
+    let mut language_encoders = LanguageEncoders::new();
+    for (language, labels) in table.iter() {
+        let language = Language::new(language)
+            .map_err(|e| anyhow!("{} in the encoders of {}.", e, file_name.as_ref()))?;
+        let key = format!("encoders.{}", language);
+        let encoders = get_option_values(&key, labels, file_name.as_ref())?
+            .iter()
+            .map(get_encoder)
+            .collect::<Result<Box<[_]>>>()?;
+        language_encoders.insert(language, encoders);
+    }
+    Ok(language_encoders)
+}
+
+impl ConfigSettings {
+    fn merge_table<S>(&mut self, table: &Table, file_name: S) -> Result<()>
+    where
+        S: AsRef<str>,
+    {
+        for (key, value) in table.iter() {
+            if key == "encoders" {
+                let Value::Table(encoders) = value else {
+                    return Err(anyhow!(
+                        "The encoders in {} must be a table of languages.",
+                        file_name.as_ref()
+                    ));
+                };
+                self.language_encoders
+                    .extend(get_language_encoders(encoders, &file_name)?);
+                continue;
+            }
+            let values = get_option_values(key, value, file_name.as_ref())?;
+            self.options.insert(
+                key.as_str().into(),
+                OptionDefault {
+                    values,
+                    file_name: file_name.as_ref().into(),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, settings: &ConfigSettings) {
+        self.options.extend(settings.options.clone());
+        self.language_encoders
+            .extend(settings.language_encoders.clone());
+    }
+
+    /// Uses the configured values as the defaults of the options with the same long name,
+    /// in the command and its subcommands. The values conflicting with an option given in
+    /// the arguments are left out, as the command line always wins.
+    pub fn apply_defaults(&self, command: Command, args: &[OsString]) -> Result<Command> {
+        // A failing parse is reported again once the defaults are applied
+        let matches = command.clone().try_get_matches_from(args).ok();
+        let (command, applied_keys) =
+            apply_option_defaults(command, matches.as_ref(), &self.options)?;
+        if let Some((key, option_default)) = self
+            .options
+            .iter()
+            .find(|(key, _)| !applied_keys.contains(&key.as_ref()))
+        {
+            return Err(anyhow!(
+                "Unknown option {} in {}.",
+                key,
+                option_default.file_name
+            ));
+        }
+        Ok(command)
+    }
+}
+
+fn validate_values(arg: &Arg, key: &str, option_default: &OptionDefault) -> Result<()> {
+    let validator = Command::new("config").no_binary_name(true).arg(
+        Arg::new("value")
+            .long("value")
+            .value_parser(arg.get_value_parser().clone())
+            .allow_hyphen_values(true),
+    );
+    for value in option_default.values.iter() {
+        if validator
+            .clone()
+            .try_get_matches_from([format!("--value={}", value)])
+            .is_ok()
+        {
+            continue;
+        }
+        let possible_values: Vec<String> = arg
+            .get_possible_values()
+            .iter()
+            .map(|possible_value| possible_value.get_name().to_owned())
+            .collect();
+        if possible_values.is_empty() {
+            return Err(anyhow!(
+                "Invalid value {} for the option {} in {}.",
+                value,
+                key,
+                option_default.file_name
+            ));
+        }
+        return Err(anyhow!(
+            "The option {} in {} must be one of {}, not {}.",
+            key,
+            option_default.file_name,
+            possible_values.join(", "),
+            value
+        ));
+    }
+    Ok(())
+}
+
+fn is_conflicting(command: &Command, arg: &Arg, other_arg: &Arg) -> bool {
+    let conflicts_with = |arg: &Arg, other_arg: &Arg| {
+        command
+            .get_arg_conflicts_with(arg)
+            .iter()
+            .any(|conflicting_arg| conflicting_arg.get_id() == other_arg.get_id())
+    };
+    conflicts_with(arg, other_arg) || conflicts_with(other_arg, arg)
+}
+
+fn apply_option_defaults<'a>(
+    mut command: Command,
+    matches: Option<&ArgMatches>,
+    options: &'a BTreeMap<Box<str>, OptionDefault>,
+) -> Result<(Command, Vec<&'a str>)> {
+    // Conflicts are only complete once the command is built
+    let mut built_command = command.clone();
+    built_command.build();
+    let explicit_args: Vec<&Arg> = built_command
+        .get_arguments()
+        .filter(|arg| {
+            matches.is_some_and(|matches| {
+                matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+            })
+        })
+        .collect();
+
+    let mut applied_keys = Vec::new();
+    let mut configured_args: Vec<(&Arg, &str, &OptionDefault)> = Vec::new();
+    for arg in built_command.get_arguments() {
+        let Some((key, option_default)) =
+            arg.get_long().and_then(|long| options.get_key_value(long))
+        else {
+            continue;
+        };
+        applied_keys.push(key.as_ref());
+        validate_values(arg, key, option_default)?;
+        if explicit_args
+            .iter()
+            .any(|explicit_arg| is_conflicting(&built_command, arg, explicit_arg))
+        {
+            continue;
+        }
+        if let Some((other_key, _, _)) = configured_args
+            .iter()
+            .find(|(other_arg, _, _)| is_conflicting(&built_command, arg, other_arg))
+        {
+            return Err(anyhow!(
+                "The options {} and {} in {} can't be used together.",
+                other_key,
+                key,
+                option_default.file_name
+            ));
+        }
+        configured_args.push((arg, key, option_default));
+    }
+
+    let configured_defaults: Vec<(String, Vec<String>)> = configured_args
+        .iter()
+        .map(|(arg, _, option_default)| (arg.get_id().to_string(), option_default.values.clone()))
+        .collect();
+    for (id, values) in configured_defaults {
+        command = command.mut_arg(id, |arg| {
+            // A flag enabled by the configuration can be turned off with --flag=false
+            let arg = match arg.get_action() {
+                ArgAction::SetTrue => arg
+                    .action(ArgAction::Set)
+                    .value_parser(value_parser!(bool))
+                    .num_args(0..=1)
+                    .require_equals(true)
+                    .default_missing_value("true"),
+                _ => arg,
+            };
+            arg.default_values(values)
+        });
+    }
+
+    let subcommand_names: Vec<String> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_owned())
+        .collect();
+    for name in subcommand_names {
+        let subcommand_matches = matches.and_then(|matches| matches.subcommand_matches(&name));
+        let mut result = Ok(Vec::new());
+        command = command.mut_subcommand(name, |subcommand| {
+            match apply_option_defaults(subcommand.clone(), subcommand_matches, options) {
+                Ok((subcommand, keys)) => {
+                    result = Ok(keys);
+                    subcommand
+                }
+                Err(e) => {
+                    result = Err(e);
+                    subcommand
+                }
+            }
+        });
+        applied_keys.extend(result?);
+    }
+    Ok((command, applied_keys))
+}
+
+/// Lists the configuration files that exist, the user one first so the project one wins.
+pub fn get_config_files() -> Vec<PathBuf> {
+    let user_config_directory = env::var_os("XDG_CONFIG_HOME")
+        .filter(|directory| !directory.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+    let user_config = user_config_directory.map(|directory| directory.join("subtrack/config.toml"));
+    let local_config = env::current_dir().ok().and_then(|directory| {
+        directory
+            .ancestors()
+            .map(|directory| directory.join(LOCAL_CONFIG_FILE_NAME))
+            .find(|file| file.is_file())
+    });
+    [user_config, local_config]
+        .into_iter()
+        .flatten()
+        .filter(|file| file.is_file())
+        .collect()
+}
+
+impl Config {
+    /// Reads the configuration files, an empty configuration when there are none.
+    pub fn load() -> Result<Self> {
+        let mut config = Config::default();
+        for file in get_config_files() {
+            let file_name = file.to_string_lossy();
+            let content = fs::read_to_string(&file).map_err(|e| {
+                anyhow!(
+                    "Failed to read the configuration file {}: {}.",
+                    file_name,
+                    e
+                )
+            })?;
+            config.merge_str(content, file_name)?;
+        }
+        Ok(config)
+    }
+
+    /// Adds a configuration file, its values overriding the previous ones.
+    pub fn merge_str<C, F>(&mut self, content: C, file_name: F) -> Result<()>
+    where
+        C: AsRef<str>,
+        F: AsRef<str>,
+    {
+        let mut table: Table = content.as_ref().parse().map_err(|e| {
+            anyhow!(
+                "Failed to parse the configuration file {}: {}",
+                file_name.as_ref(),
+                e
+            )
+        })?;
+
+        if let Some(profile) = table.remove("profile") {
+            let Value::String(profile) = profile else {
+                return Err(anyhow!(
+                    "The profile in {} must be the name of a profile.",
+                    file_name.as_ref()
+                ));
+            };
+            self.default_profile = Some(profile.into());
+        }
+        if let Some(profiles) = table.remove("profiles") {
+            let Value::Table(profiles) = profiles else {
+                return Err(anyhow!(
+                    "The profiles in {} must be a table of profiles.",
+                    file_name.as_ref()
+                ));
+            };
+            for (name, profile) in profiles.iter() {
+                let Value::Table(profile) = profile else {
+                    return Err(anyhow!(
+                        "The profile {} in {} must be a table of options.",
+                        name,
+                        file_name.as_ref()
+                    ));
+                };
+                self.profiles
+                    .entry(name.as_str().into())
+                    .or_default()
+                    .merge_table(profile, &file_name)?;
+            }
+        }
+        self.settings.merge_table(&table, file_name)
+    }
+
+    /// Gets the settings to use, with the ones of the profile when one is chosen on the
+    /// command line or by the configuration.
+    pub fn get_settings(&self, profile: Option<&str>) -> Result<ConfigSettings> {
+        let mut settings = self.settings.clone();
+        if let Some(profile) = profile.or(self.default_profile.as_deref()) {
+            let profile_settings = self.profiles.get(profile).ok_or_else(|| {
+                anyhow!(
+                    "The profile {} is not defined in the configuration files.",
+                    profile
+                )
+            })?;
+            settings.merge(profile_settings);
+        }
+        Ok(settings)
+    }
+}
+
+/// Finds the profile chosen with --profile before parsing the command line, as it decides
+/// the defaults of the other options.
+pub fn get_profile_argument<S>(args: &[S]) -> Option<&str>
+where
+    S: AsRef<str>,
+{
+    let mut args = args
+        .iter()
+        .map(|arg| arg.as_ref())
+        .take_while(|arg| *arg != "--");
+    while let Some(arg) = args.next() {
+        if arg == "--profile" {
+            return args.next();
+        }
+        if let Some(profile) = arg.strip_prefix("--profile=") {
+            return Some(profile);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{Args, Parser, Subcommand};
+
+    use super::*;
+
+    #[derive(Parser, Debug)]
+    struct TestCli {
+        #[command(subcommand)]
+        command: Option<TestCommand>,
+
+        #[arg(short, long, conflicts_with = "in_place")]
+        output_file: Option<Box<str>>,
+
+        #[command(flatten)]
+        options: TestOptions,
+    }
+
+    #[derive(Args, Debug)]
+    struct TestOptions {
+        #[arg(long, default_value = "-subs")]
+        output_suffix: Box<str>,
+
+        #[arg(long, conflicts_with_all = ["output_suffix", "in_place"])]
+        output_template: Option<Box<str>>,
+
+        #[arg(long)]
+        in_place: bool,
+
+        #[arg(long)]
+        dry_run: bool,
+    }
+
+    #[derive(Subcommand, Debug)]
+    enum TestCommand {
+        Batch {
+            #[arg(long, default_value_t = 1)]
+            jobs: usize,
+
+            #[command(flatten)]
+            options: TestOptions,
+        },
+    }
+
+    fn parse<S>(settings: &ConfigSettings, args: &[S]) -> Result<TestCli>
+    where
+        S: AsRef<str>,
+    {
+        use clap::{CommandFactory, FromArgMatches};
+
+        let args: Vec<OsString> = args.iter().map(|arg| arg.as_ref().into()).collect();
+        let command = settings.apply_defaults(TestCli::command(), &args)?;
+        let matches = command.try_get_matches_from(args)?;
+        Ok(TestCli::from_arg_matches(&matches)?)
+    }
+
+    #[test]
+    fn test_config_sets_defaults_of_every_command() -> Result<()> {
+        let mut config = Config::default();
+        config.merge_str(
+            "output-suffix = \".en\"\ndry-run = true\njobs = 4",
+            "config.toml",
+        )?;
+        let settings = config.get_settings(None)?;
+
+        let cli = parse(&settings, &["test"])?;
+        assert_eq!(cli.options.output_suffix.as_ref(), ".en");
+        assert!(cli.options.dry_run);
+
+        let cli = parse(&settings, &["test", "batch", "--output-suffix", ".x"])?;
+        let Some(TestCommand::Batch { jobs, options }) = cli.command else {
+            panic!("The batch command was not parsed.");
+        };
+        assert_eq!(jobs, 4);
+        assert_eq!(options.output_suffix.as_ref(), ".x");
+        Ok(())
+    }
+
+    #[test]
+    fn test_arguments_win_over_conflicting_config() -> Result<()> {
+        let mut config = Config::default();
+        config.merge_str("in-place = true", "config.toml")?;
+        let settings = config.get_settings(None)?;
+        let cli = parse(&settings, &["test"])?;
+        assert!(cli.options.in_place);
+        let cli = parse(&settings, &["test", "-o", "out.mkv"])?;
+        assert!(!cli.options.in_place);
+        assert_eq!(cli.output_file.as_deref(), Some("out.mkv"));
+
+        let mut config = Config::default();
+        config.merge_str("output-template = \"{stem}.{ext}\"", "config.toml")?;
+        let cli = parse(&config.get_settings(None)?, &["test", "--output-suffix=.x"])?;
+        assert_eq!(cli.options.output_template, None);
+        assert_eq!(cli.options.output_suffix.as_ref(), ".x");
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_flags_can_be_turned_off() -> Result<()> {
+        let mut config = Config::default();
+        config.merge_str("dry-run = true", "config.toml")?;
+        let settings = config.get_settings(None)?;
+        assert!(
+            !parse(&settings, &["test", "--dry-run=false"])?
+                .options
+                .dry_run
+        );
+        assert!(parse(&settings, &["test", "--dry-run"])?.options.dry_run);
+        assert!(parse(&settings, &["test", "batch"])?.command.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_config_values_name_the_file() -> Result<()> {
+        let mut config = Config::default();
+        config.merge_str("jobs = \"many\"", "config.toml")?;
+        let e = parse(&config.get_settings(None)?, &["test"]).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Invalid value many for the option jobs in config.toml."
+        );
+
+        let mut config = Config::default();
+        config.merge_str("in-place = true\noutput-template = \"x\"", "config.toml")?;
+        assert!(parse(&config.get_settings(None)?, &["test"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_project_config_and_profile_override() -> Result<()> {
+        let mut config = Config::default();
+        config.merge_str(
+            "output-suffix = \".user\"\n[profiles.anime]\noutput-suffix = \".anime\"",
+            "config.toml",
+        )?;
+        config.merge_str("output-suffix = \".project\"", ".subtrack.toml")?;
+
+        let cli = parse(&config.get_settings(None)?, &["test"])?;
+        assert_eq!(cli.options.output_suffix.as_ref(), ".project");
+        let cli = parse(&config.get_settings(Some("anime"))?, &["test"])?;
+        assert_eq!(cli.options.output_suffix.as_ref(), ".anime");
+        assert!(config.get_settings(Some("missing")).is_err());
+
+        config.merge_str("profile = \"anime\"", ".subtrack.toml")?;
+        let cli = parse(&config.get_settings(None)?, &["test"])?;
+        assert_eq!(cli.options.output_suffix.as_ref(), ".anime");
+        Ok(())
+    }
+
+    #[test]
+    fn test_language_encoders() -> Result<()> {
+        let mut config = Config::default();
+        config.merge_str(
+            "[encoders]\nspanish = [\"windows-1252\", \"latin1\"]",
+            "config.toml",
+        )?;
+        let settings = config.get_settings(None)?;
+        assert_eq!(
+            settings.language_encoders[&Language::Spanish].as_ref(),
+            [encoding_rs::WINDOWS_1252, encoding_rs::WINDOWS_1252]
+        );
+        assert!(config
+            .merge_str("[encoders]\nspanish = \"unknown\"", "config.toml")
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_unknown_option() {
+        let mut config = Config::default();
+        config
+            .merge_str("output-sufix = \".en\"", "config.toml")
+            .unwrap();
+        parse(&config.get_settings(None).unwrap(), &["test"]).unwrap();
+    }
+
+    #[test]
+    fn test_profile_argument() {
+        assert_eq!(
+            get_profile_argument(&["subtrack", "--profile", "anime", "video.mkv"]),
+            Some("anime")
+        );
+        assert_eq!(
+            get_profile_argument(&["subtrack", "batch", "--profile=anime"]),
+            Some("anime")
+        );
+        assert_eq!(get_profile_argument(&["subtrack", "--", "--profile"]), None);
+    }
+}
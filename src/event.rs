@@ -0,0 +1,193 @@
+use std::sync::mpsc::Sender;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    external::ffmpeg::progress::FfmpegProgress, muxer::plan::MuxStep, subtitle::language::Language,
+    video::container::StreamChange,
+};
+
+/// Something that happened while adding subtitles to a video, serialized with its name in
+/// the `event` field.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum MuxEvent {
+    /// The steps about to run, sent before any of them.
+    Planned { steps: Vec<MuxStep> },
+
+    /// The input video is being checked.
+    VideoBuilding,
+
+    /// The output video was chosen.
+    OutputResolved { file_name: Box<str> },
+
+    /// The output video already exists and is skipped.
+    OutputSkipped,
+
+    /// The streams of the input video are being read.
+    VideoProbing,
+
+    /// The subtitle at the given position was decoded from another encoding or had its
+    /// line endings fixed, writing the result to a new file.
+    EncodingConverted {
+        index: usize,
+        file_name: Box<str>,
+        encoding: &'static str,
+        converted_file: Box<str>,
+    },
+
+    /// The subtitle at the given position is ready to be added.
+    SubtitleParsed {
+        index: usize,
+        file_name: Box<str>,
+        language: Option<Language>,
+    },
+
+    /// Streams of the input video are converted or dropped to fit in the output container.
+    StreamsChanged { changes: Vec<StreamChange> },
+
+    /// ffmpeg started, with the duration of the video when known.
+    FfmpegStarted {
+        args: Vec<String>,
+        duration: Option<f64>,
+        burn_in: bool,
+    },
+
+    /// ffmpeg reported how much of the video it processed.
+    FfmpegProgress(FfmpegProgress),
+
+    /// ffmpeg wrote a line to its log, how many depends on its log level.
+    FfmpegLog { line: Box<str> },
+
+    /// ffmpeg wrote the output video.
+    FfmpegFinished,
+
+    /// Nothing was written as a dry run was asked.
+    DryRun {
+        args: Vec<String>,
+        fixed_subtitle_files: Vec<Box<str>>,
+    },
+
+    /// The output video is being probed to check every subtitle made it.
+    OutputVerifying,
+
+    /// The input video was replaced with the modified one.
+    InPlaceReplaced {
+        original_file: Box<str>,
+        backup_file: Option<Box<str>>,
+    },
+
+    /// The subtitles were added to the output video.
+    Finished { output_file: Box<str> },
+
+    /// Adding the subtitles failed with the given error.
+    Failed { error: Box<str> },
+}
+
+/// Receives the events of the pipeline, to show its progress in a terminal, a GUI or a server.
+pub trait EventSink {
+    fn send(&self, event: MuxEvent) -> Result<()>;
+}
+
+/// Ignores every event.
+pub struct SilentSink;
+
+impl EventSink for SilentSink {
+    fn send(&self, _event: MuxEvent) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Forwards every event to a channel, so another thread can observe the progress.
+pub struct ChannelSink {
+    sender: Sender<MuxEvent>,
+}
+
+impl ChannelSink {
+    pub fn new(sender: Sender<MuxEvent>) -> Self {
+        ChannelSink { sender }
+    }
+}
+
+impl EventSink for ChannelSink {
+    fn send(&self, event: MuxEvent) -> Result<()> {
+        let _ = self.sender.send(event);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, time::Duration};
+
+    use super::*;
+    use crate::video::{container::StreamAction, stream::StreamKind};
+
+    #[test]
+    fn test_channel_sink_forwards_events() -> Result<()> {
+        let (sender, receiver) = mpsc::channel();
+        let sink = ChannelSink::new(sender);
+        sink.send(MuxEvent::VideoBuilding)?;
+        sink.send(MuxEvent::FfmpegFinished)?;
+        drop(sink);
+
+        let events: Vec<MuxEvent> = receiver.iter().collect();
+        assert_eq!(events, [MuxEvent::VideoBuilding, MuxEvent::FfmpegFinished]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_events_are_tagged_json() -> Result<()> {
+        let event = MuxEvent::SubtitleParsed {
+            index: 0,
+            file_name: "movie.srt".into(),
+            language: Some(Language::French),
+        };
+        assert_eq!(
+            serde_json::to_string(&event)?,
+            r#"{"event":"subtitle_parsed","index":0,"file_name":"movie.srt","language":"french"}"#
+        );
+
+        let event = MuxEvent::FfmpegProgress(FfmpegProgress {
+            out_time: Duration::from_millis(1500),
+            speed: Some(2.0),
+            is_finished: false,
+        });
+        assert_eq!(
+            serde_json::to_string(&event)?,
+            r#"{"event":"ffmpeg_progress","out_time":1.5,"speed":2.0,"is_finished":false}"#
+        );
+
+        let event = MuxEvent::Planned {
+            steps: vec![MuxStep::Subtitle { index: 0 }, MuxStep::Mux],
+        };
+        assert_eq!(
+            serde_json::to_string(&event)?,
+            r#"{"event":"planned","steps":[{"step":"subtitle","index":0},{"step":"mux"}]}"#
+        );
+
+        let event = MuxEvent::StreamsChanged {
+            changes: vec![StreamChange {
+                index: 2,
+                kind: StreamKind::Audio,
+                codec: Some("truehd".into()),
+                action: StreamAction::Convert {
+                    target_codec: "aac",
+                },
+            }],
+        };
+        assert_eq!(
+            serde_json::to_string(&event)?,
+            r#"{"event":"streams_changed","changes":[{"index":2,"kind":"audio","codec":"truehd","action":"convert","target_codec":"aac"}]}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_channel_sink_without_receiver() -> Result<()> {
+        let (sender, receiver) = mpsc::channel();
+        drop(receiver);
+        ChannelSink::new(sender).send(MuxEvent::VideoBuilding)
+    }
+}
@@ -1 +1,2 @@
 pub mod ffmpeg;
+pub mod ffprobe;
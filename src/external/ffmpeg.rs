@@ -1,35 +1,79 @@
-use std::process::Command;
+pub mod progress;
 
-use anyhow::Result;
+use std::{
+    cell::RefCell,
+    fmt,
+    io::{BufRead, BufReader},
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+};
+
+use anyhow::{anyhow, Result};
 
 use crate::{
-    behavior::Behavior,
-    logger::CLILogger,
-    subtitle::file::SubtitleFile,
-    video::{file::VideoFile, format::VideoFormat},
+    behavior,
+    event::{EventSink, MuxEvent},
+    subtitle::{file::SubtitleFile, format::SubtitleFormat, order::SubtitleTrack},
+    utils::shell_quote,
+    video::{
+        container::ContainerConversion, encoder::VideoEncoder, file::VideoFile, format::VideoFormat,
+    },
 };
+use progress::{FfmpegProgress, ProgressParser};
 
-fn get_args_for_adding_subtitles<'a, S>(
+/// How much ffmpeg writes to its own log.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FfmpegLogLevel {
+    Fatal,
+    #[default]
+    Error,
+    Info,
+    Debug,
+}
+
+impl fmt::Display for FfmpegLogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FfmpegLogLevel::Fatal => write!(f, "fatal"),
+            FfmpegLogLevel::Error => write!(f, "error"),
+            FfmpegLogLevel::Info => write!(f, "info"),
+            FfmpegLogLevel::Debug => write!(f, "debug"),
+        }
+    }
+}
+
+fn get_base_args(video_file: &VideoFile, log_level: FfmpegLogLevel) -> Vec<String> {
+    Vec::from([
+        "-hide_banner".to_owned(),
+        "-loglevel".to_owned(),
+        log_level.to_string(),
+        // Report machine readable progress instead of the stats line
+        "-progress".to_owned(),
+        "pipe:1".to_owned(),
+        "-nostats".to_owned(),
+        "-i".to_owned(),
+        video_file.get_file_name().into(),
+    ])
+}
+
+pub fn get_args_for_adding_subtitles<'a, S>(
     video_file: &'a VideoFile,
     subtitles: S,
+    tracks: &[SubtitleTrack],
     output_file: &'a VideoFile,
-    behavior: &Behavior,
-) -> Vec<String>
+    conversion: &ContainerConversion,
+    log_level: FfmpegLogLevel,
+) -> Result<Vec<String>>
 where
     S: AsRef<[SubtitleFile]>,
 {
-    let mut args = Vec::from([
-        "-hide_banner".to_owned(),
-        "-loglevel".to_owned(),
-        "error".to_owned(),
-        "-i".to_owned(),
-        video_file.get_file_name().into(),
-    ]);
+    let mut args = get_base_args(video_file, log_level);
 
     for sub in subtitles.as_ref().iter() {
         args.extend([
             "-f".to_owned(),
-            sub.format.to_extension().into(),
+            sub.format.to_ffmpeg_format().into(),
             "-i".to_owned(),
             sub.file_name.clone().into(),
         ]);
@@ -38,18 +82,25 @@ where
     // Map all input streams to not lose metadata but remove subtitles
     args.extend(["-map".into(), "0".into(), "-map".into(), "-0:s".into()]);
 
-    // Avoid mapping data stream for conversion between different formats
-    if video_file.format != output_file.format {
-        args.extend(["-map".into(), "-0:d".into()]);
-    }
+    // Leave out the streams the output container can't store
+    args.extend(conversion.get_map_args());
 
-    // Map all provided subtitles
-    for i in 0..subtitles.as_ref().len() {
-        args.extend(["-map".to_owned(), format!("{}", i + 1).into()]);
+    // Map the subtitle tracks chosen by the behavior in their output order
+    for track in tracks.iter() {
+        let map_specifier = match track {
+            // The new subtitles are the inputs after the video file
+            SubtitleTrack::New(i) => format!("{}", i + 1),
+            SubtitleTrack::Existing(stream) => format!("0:{}", stream.index),
+        };
+        args.extend(["-map".into(), map_specifier]);
     }
 
-    // Add subtitles based on behavior selected by user
-    args.extend(behavior.get_args_for_adding_subtitles(&subtitles));
+    // Add the metadata of the subtitles
+    args.extend(behavior::get_args_for_adding_subtitles(
+        &subtitles,
+        tracks,
+        &output_file.format,
+    )?);
 
     // Copy all previous streams to output file
     // It is important this is used first before the -c:s mov_text option
@@ -60,33 +111,365 @@ where
     if output_file.format == VideoFormat::MP4 {
         args.extend(["-c:s".into(), "mov_text".into()]);
     }
+    args.extend(conversion.get_codec_args());
 
     // Copy all previous streams to output file
+    if output_file.overwrite_existing {
+        args.push("-y".into());
+    }
     args.push(output_file.file_name.clone().into());
 
-    return args;
+    Ok(args)
 }
 
-pub fn add_subtitles_to_video<S>(
-    video_file: &VideoFile,
-    subtitles: S,
-    output_file: &VideoFile,
-    behavior: &Behavior,
-    logger: &CLILogger,
-) -> Result<()>
+fn escape_filter_value(value: &str) -> String {
+    let escape = |value: &str, special_characters: &[char]| {
+        value.chars().fold(String::new(), |mut escaped, c| {
+            if special_characters.contains(&c) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+            escaped
+        })
+    };
+    let option_value = escape(value, &['\\', '\'', ':']);
+    escape(&option_value, &['\\', '\'', '[', ']', ',', ';'])
+}
+
+pub fn get_args_for_burning_subtitle<'a>(
+    video_file: &'a VideoFile,
+    subtitle: &'a SubtitleFile,
+    tracks: &[SubtitleTrack],
+    output_file: &'a VideoFile,
+    encoder: &VideoEncoder,
+    conversion: &ContainerConversion,
+    log_level: FfmpegLogLevel,
+) -> Vec<String> {
+    let mut args = get_base_args(video_file, log_level);
+
+    // Only the main video stream is filtered, other video streams like covers would fail
+    args.extend(["-map".into(), "0:v:0".into(), "-map".into(), "0:a?".into()]);
+
+    // Keep the pre-existing subtitle tracks chosen by the behavior as soft subtitles
+    for track in tracks.iter() {
+        if let SubtitleTrack::Existing(stream) = track {
+            args.extend(["-map".into(), format!("0:{}", stream.index)]);
+        }
+    }
+
+    // Draw the fixed UTF-8 subtitle on top of the video frames, keeping the ASS styles
+    let filter = match subtitle.format {
+        SubtitleFormat::Srt => "subtitles",
+        SubtitleFormat::Ass | SubtitleFormat::Ssa => "ass",
+    };
+    args.extend([
+        "-vf".into(),
+        format!(
+            "{}=filename={}",
+            filter,
+            escape_filter_value(&subtitle.file_name)
+        ),
+    ]);
+    args.extend(encoder.get_args());
+    args.extend(["-c:a".into(), "copy".into()]);
+    if output_file.format == VideoFormat::MP4 {
+        args.extend(["-c:s".into(), "mov_text".into()]);
+    } else {
+        args.extend(["-c:s".into(), "copy".into()]);
+    }
+    args.extend(conversion.get_codec_args());
+
+    if output_file.overwrite_existing {
+        args.push("-y".into());
+    }
+    args.push(output_file.file_name.clone().into());
+    args
+}
+
+/// Formats the ffmpeg invocation so it can be pasted in a shell.
+pub fn get_shell_command(args: &[String]) -> String {
+    std::iter::once("ffmpeg")
+        .chain(args.iter().map(|arg| arg.as_str()))
+        .map(shell_quote)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn run_ffmpeg_with_progress<F, L>(args: &[String], mut on_progress: F, mut on_log: L) -> Result<()>
 where
-    S: AsRef<[SubtitleFile]>,
+    F: FnMut(&FfmpegProgress),
+    L: FnMut(&str),
 {
-    let bar = logger.report_ffmpeg_started()?;
-    Command::new("ffmpeg")
-        .args(get_args_for_adding_subtitles(
-            video_file,
+    let mut child = Command::new("ffmpeg")
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("Failed to run ffmpeg: {}", e))?;
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("Failed to read the progress of ffmpeg."))?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| anyhow!("Failed to read the log of ffmpeg."))?;
+
+    // Read the log on its own thread so a full stderr pipe never blocks ffmpeg
+    let (log_sender, log_receiver) = mpsc::channel();
+    let mut last_log_line = None;
+    let read_result = thread::scope(|scope| -> Result<()> {
+        scope.spawn(move || {
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                if log_sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut handle_log = |line: String| {
+            on_log(&line);
+            if !line.trim().is_empty() {
+                last_log_line = Some(line);
+            }
+        };
+        let mut parser = ProgressParser::default();
+        for line in BufReader::new(stdout).lines() {
+            log_receiver.try_iter().for_each(&mut handle_log);
+            // Stop ffmpeg so its log closes and the log thread ends
+            let line = line.inspect_err(|_| {
+                let _ = child.kill();
+            })?;
+            if let Some(progress) = parser.parse_line(line) {
+                on_progress(&progress);
+            }
+        }
+        log_receiver.iter().for_each(handle_log);
+        Ok(())
+    });
+
+    let status = child.wait()?;
+    read_result.map_err(|e| anyhow!("Failed to read the progress of ffmpeg: {}", e))?;
+    if !status.success() {
+        return Err(match last_log_line {
+            Some(line) => anyhow!("ffmpeg failed with {}: {}", status, line.trim()),
+            None => anyhow!("ffmpeg failed with {}.", status),
+        });
+    }
+    Ok(())
+}
+
+/// Runs ffmpeg with the arguments to add or burn in subtitles, reporting its progress and log.
+pub fn run_ffmpeg_with_events(
+    args: &[String],
+    duration: Option<f64>,
+    burn_in: bool,
+    event_sink: &dyn EventSink,
+) -> Result<()> {
+    event_sink.send(MuxEvent::FfmpegStarted {
+        args: args.to_vec(),
+        duration,
+        burn_in,
+    })?;
+
+    // Keep reading the output of ffmpeg when an event fails, reporting it at the end
+    let event_result = RefCell::new(Ok(()));
+    let send = |event| {
+        let mut event_result = event_result.borrow_mut();
+        if event_result.is_ok() {
+            *event_result = event_sink.send(event);
+        }
+    };
+    run_ffmpeg_with_progress(
+        args,
+        |progress| send(MuxEvent::FfmpegProgress(progress.clone())),
+        |line| send(MuxEvent::FfmpegLog { line: line.into() }),
+    )?;
+    event_result.into_inner()?;
+    event_sink.send(MuxEvent::FfmpegFinished)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        subtitle::{disposition::Disposition, handling::SubtitleHandling, language::Language},
+        video::stream::{StreamInfo, StreamKind},
+    };
+
+    #[test]
+    fn test_subtitle_tracks_are_mapped_in_order() -> Result<()> {
+        let video_file = VideoFile {
+            file_name: "input.mkv".into(),
+            format: VideoFormat::MKV,
+            overwrite_existing: false,
+        };
+        let output_file = VideoFile {
+            file_name: "output.mkv".into(),
+            format: VideoFormat::MKV,
+            overwrite_existing: false,
+        };
+        let subtitles = [SubtitleFile {
+            language: Some(Language::English),
+            disposition: Disposition::default(),
+            title: None,
+            format: SubtitleFormat::Srt,
+            file_name: "subtitle.srt".into(),
+            mode: SubtitleHandling::Keep,
+            is_original_subtitle_file: true,
+            is_written: true,
+        }];
+        let stream = StreamInfo {
+            index: 3,
+            kind: StreamKind::Subtitle,
+            codec: Some("subrip".into()),
+            language: Some("spa".into()),
+            ..Default::default()
+        };
+        let tracks = [SubtitleTrack::Existing(&stream), SubtitleTrack::New(0)];
+
+        let args = get_args_for_adding_subtitles(
+            &video_file,
             &subtitles,
+            &tracks,
             &output_file,
-            behavior,
-        ))
-        .spawn()?
-        .wait()?;
-    logger.finish_ffmpeg(&bar)?;
-    Ok(())
+            &ContainerConversion::default(),
+            FfmpegLogLevel::default(),
+        )?;
+        assert_eq!(
+            args[16..24],
+            [
+                "-map",
+                "0:3",
+                "-map",
+                "1",
+                "-metadata:s:s:1",
+                "language=eng",
+                "-c",
+                "copy"
+            ]
+            .map(String::from)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape_filter_value() {
+        assert_eq!(escape_filter_value("subtitle.srt"), "subtitle.srt");
+        assert_eq!(
+            escape_filter_value("C:\\subs\\it's [1].srt"),
+            "C\\\\:\\\\\\\\subs\\\\\\\\it\\\\\\'s \\[1\\].srt"
+        );
+    }
+
+    #[test]
+    fn test_burn_in_maps_only_kept_subtitles() {
+        let video_file = VideoFile {
+            file_name: "input.mkv".into(),
+            format: VideoFormat::MKV,
+            overwrite_existing: false,
+        };
+        let output_file = VideoFile {
+            file_name: "output.mp4".into(),
+            format: VideoFormat::MP4,
+            overwrite_existing: false,
+        };
+        let subtitle = SubtitleFile {
+            language: None,
+            disposition: Disposition::default(),
+            title: None,
+            format: SubtitleFormat::Srt,
+            file_name: "subtitle.srt".into(),
+            mode: SubtitleHandling::Keep,
+            is_original_subtitle_file: true,
+            is_written: true,
+        };
+        let encoder = VideoEncoder {
+            codec: "libx264".into(),
+            crf: 20,
+            preset: "fast".into(),
+        };
+        let tracks = [SubtitleTrack::New(0)];
+
+        let args = get_args_for_burning_subtitle(
+            &video_file,
+            &subtitle,
+            &tracks,
+            &output_file,
+            &encoder,
+            &ContainerConversion::default(),
+            FfmpegLogLevel::Info,
+        );
+        assert_eq!(args[1..3], ["-loglevel", "info"].map(String::from));
+        assert_eq!(
+            args[8..],
+            [
+                "-map",
+                "0:v:0",
+                "-map",
+                "0:a?",
+                "-vf",
+                "subtitles=filename=subtitle.srt",
+                "-c:v",
+                "libx264",
+                "-crf",
+                "20",
+                "-preset",
+                "fast",
+                "-c:a",
+                "copy",
+                "-c:s",
+                "mov_text",
+                "output.mp4"
+            ]
+            .map(String::from)
+        );
+    }
+
+    #[test]
+    fn test_burn_in_draws_ass_subtitles_with_their_styles() {
+        let video_file = VideoFile {
+            file_name: "input.mkv".into(),
+            format: VideoFormat::MKV,
+            overwrite_existing: false,
+        };
+        let output_file = VideoFile {
+            file_name: "output.mkv".into(),
+            format: VideoFormat::MKV,
+            overwrite_existing: false,
+        };
+        let subtitle = SubtitleFile {
+            language: None,
+            disposition: Disposition::default(),
+            title: None,
+            format: SubtitleFormat::Ass,
+            file_name: "subtitle.ass".into(),
+            mode: SubtitleHandling::Keep,
+            is_original_subtitle_file: true,
+            is_written: true,
+        };
+
+        let args = get_args_for_burning_subtitle(
+            &video_file,
+            &subtitle,
+            &[SubtitleTrack::New(0)],
+            &output_file,
+            &VideoEncoder::default(),
+            &ContainerConversion::default(),
+            FfmpegLogLevel::default(),
+        );
+        assert!(args
+            .windows(2)
+            .any(|pair| pair == ["-vf", "ass=filename=subtitle.ass"]));
+    }
+
+    #[test]
+    fn test_shell_command() {
+        let args = ["-i", "my video.mkv", "-map", "0:s?"].map(String::from);
+        assert_eq!(
+            get_shell_command(&args),
+            "ffmpeg -i 'my video.mkv' -map '0:s?'"
+        );
+    }
 }
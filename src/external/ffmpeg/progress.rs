@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+
+/// A progress report written by ffmpeg when running with `-progress`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct FfmpegProgress {
+    /// Timestamp of the output processed so far.
+    #[serde(serialize_with = "serialize_seconds")]
+    pub out_time: Duration,
+
+    /// Processing speed relative to real time.
+    pub speed: Option<f64>,
+
+    /// Whether ffmpeg finished processing the input.
+    pub is_finished: bool,
+}
+
+fn serialize_seconds<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// Accumulates the `key=value` lines of the ffmpeg progress output into reports.
+#[derive(Default)]
+pub struct ProgressParser {
+    progress: FfmpegProgress,
+}
+
+impl ProgressParser {
+    /// Parses a line, returning a report when ffmpeg finishes writing one.
+    pub fn parse_line<S>(&mut self, line: S) -> Option<FfmpegProgress>
+    where
+        S: AsRef<str>,
+    {
+        let (key, value) = line.as_ref().trim().split_once('=')?;
+        match key {
+            "out_time_us" => {
+                // ffmpeg reports N/A or negative values before the first frame
+                if let Ok(microseconds) = value.parse::<u64>() {
+                    self.progress.out_time = Duration::from_micros(microseconds);
+                }
+            }
+            "speed" => {
+                self.progress.speed = value.trim_end_matches('x').trim().parse().ok();
+            }
+            "progress" => {
+                self.progress.is_finished = value == "end";
+                return Some(self.progress.clone());
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_progress_blocks() {
+        let mut parser = ProgressParser::default();
+        let output = [
+            "frame=10",
+            "out_time_us=N/A",
+            "speed=N/A",
+            "progress=continue",
+            "frame=250",
+            "out_time_us=10500000",
+            "speed=2.5x",
+            "progress=continue",
+            "out_time_us=20000000",
+            "speed= 3x",
+            "progress=end",
+        ];
+        let reports: Vec<FfmpegProgress> = output
+            .iter()
+            .filter_map(|line| parser.parse_line(line))
+            .collect();
+
+        assert_eq!(
+            reports,
+            vec![
+                FfmpegProgress::default(),
+                FfmpegProgress {
+                    out_time: Duration::from_millis(10500),
+                    speed: Some(2.5),
+                    is_finished: false,
+                },
+                FfmpegProgress {
+                    out_time: Duration::from_secs(20),
+                    speed: Some(3.0),
+                    is_finished: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ignore_invalid_lines() {
+        let mut parser = ProgressParser::default();
+        assert_eq!(parser.parse_line("not a progress line"), None);
+        assert_eq!(parser.parse_line("out_time_us=-1"), None);
+    }
+}
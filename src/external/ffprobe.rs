@@ -0,0 +1,216 @@
+use std::{collections::HashMap, process::Command};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+use crate::video::stream::{MediaInfo, StreamInfo, StreamKind};
+
+#[derive(Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    index: usize,
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    duration: Option<String>,
+    nb_read_packets: Option<String>,
+    #[serde(default)]
+    disposition: HashMap<String, u8>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+}
+
+impl ProbeStream {
+    fn get_tag(&self, name: &str) -> Option<&str> {
+        // Matroska files usually have upper case tags
+        self.tags
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn has_disposition(&self, name: &str) -> bool {
+        self.disposition.get(name).is_some_and(|value| *value != 0)
+    }
+
+    fn get_duration(&self) -> Option<f64> {
+        // Streams in Matroska files only store their duration as a tag
+        self.duration
+            .as_deref()
+            .and_then(parse_seconds)
+            .or_else(|| self.get_tag("duration").and_then(parse_timestamp))
+    }
+}
+
+impl From<ProbeStream> for StreamInfo {
+    fn from(stream: ProbeStream) -> Self {
+        StreamInfo {
+            index: stream.index,
+            kind: StreamKind::new(stream.codec_type.as_deref().unwrap_or_default()),
+            codec: stream.codec_name.as_deref().map(|s| s.into()),
+            language: stream.get_tag("language").map(|s| s.into()),
+            title: stream.get_tag("title").map(|s| s.into()),
+            default: stream.has_disposition("default"),
+            forced: stream.has_disposition("forced"),
+            hearing_impaired: stream.has_disposition("hearing_impaired"),
+            duration: stream.get_duration(),
+            packets: stream
+                .nb_read_packets
+                .as_deref()
+                .and_then(|packets| packets.parse().ok()),
+        }
+    }
+}
+
+fn parse_seconds(seconds: &str) -> Option<f64> {
+    seconds.parse().ok()
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<f64> {
+    let mut parts = timestamp.rsplitn(3, ':');
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next().map_or(Ok(0.0), |m| m.parse()).ok()?;
+    let hours: f64 = parts.next().map_or(Ok(0.0), |h| h.parse()).ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+fn parse_probe_output(output: &str) -> Result<MediaInfo> {
+    let probe_output: ProbeOutput = serde_json::from_str(output)
+        .map_err(|e| anyhow!("Failed to parse the output of ffprobe: {}", e))?;
+    Ok(MediaInfo {
+        duration: probe_output
+            .format
+            .and_then(|format| format.duration)
+            .as_deref()
+            .and_then(parse_seconds),
+        streams: probe_output.streams.into_iter().map(|s| s.into()).collect(),
+    })
+}
+
+pub fn probe<S>(file_name: S) -> Result<MediaInfo>
+where
+    S: AsRef<str>,
+{
+    run_probe(file_name, false)
+}
+
+/// Probes the file also counting the packets of every stream, which reads the whole file.
+pub fn probe_with_packet_counts<S>(file_name: S) -> Result<MediaInfo>
+where
+    S: AsRef<str>,
+{
+    run_probe(file_name, true)
+}
+
+fn run_probe<S>(file_name: S, count_packets: bool) -> Result<MediaInfo>
+where
+    S: AsRef<str>,
+{
+    let mut command = Command::new("ffprobe");
+    command.args([
+        "-hide_banner",
+        "-loglevel",
+        "error",
+        "-print_format",
+        "json",
+        "-show_format",
+        "-show_streams",
+    ]);
+    if count_packets {
+        command.arg("-count_packets");
+    }
+    let output = command
+        .arg(file_name.as_ref())
+        .output()
+        .map_err(|e| anyhow!("Failed to run ffprobe: {}", e))?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ffprobe failed to read {}: {}",
+            file_name.as_ref(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    parse_probe_output(&String::from_utf8_lossy(&output.stdout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROBE_OUTPUT: &str = r#"{
+        "streams": [
+            {
+                "index": 0,
+                "codec_name": "h264",
+                "codec_type": "video",
+                "disposition": { "default": 1, "forced": 0 },
+                "tags": { "DURATION": "00:01:30.500000000" }
+            },
+            {
+                "index": 1,
+                "codec_name": "aac",
+                "codec_type": "audio",
+                "duration": "90.400000",
+                "disposition": { "default": 1, "forced": 0 },
+                "tags": { "language": "eng" }
+            },
+            {
+                "index": 2,
+                "codec_name": "subrip",
+                "codec_type": "subtitle",
+                "nb_read_packets": "42",
+                "disposition": { "default": 0, "forced": 1, "hearing_impaired": 1 },
+                "tags": { "language": "spa", "title": "Spanish (Forced)" }
+            }
+        ],
+        "format": { "duration": "90.500000" }
+    }"#;
+
+    #[test]
+    fn test_parse_probe_output() -> Result<()> {
+        let media_info = parse_probe_output(PROBE_OUTPUT)?;
+        assert_eq!(media_info.duration, Some(90.5));
+        assert_eq!(media_info.streams.len(), 3);
+
+        let video = &media_info.streams[0];
+        assert_eq!(video.kind, StreamKind::Video);
+        assert_eq!(video.duration, Some(90.5));
+        assert!(video.default);
+
+        let subtitle = &media_info.streams[2];
+        assert_eq!(subtitle.kind, StreamKind::Subtitle);
+        assert_eq!(subtitle.codec.as_deref(), Some("subrip"));
+        assert_eq!(subtitle.language.as_deref(), Some("spa"));
+        assert_eq!(subtitle.title.as_deref(), Some("Spanish (Forced)"));
+        assert!(!subtitle.default);
+        assert!(subtitle.forced);
+        assert!(subtitle.hearing_impaired);
+        assert_eq!(subtitle.packets, Some(42));
+        assert_eq!(video.packets, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("01:02:03.5"), Some(3723.5));
+        assert_eq!(parse_timestamp("12.25"), Some(12.25));
+        assert_eq!(parse_timestamp("invalid"), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_parse_invalid_probe_output() {
+        parse_probe_output("not json").unwrap();
+    }
+}
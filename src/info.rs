@@ -0,0 +1,130 @@
+use std::fmt;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use console::{style, Term};
+
+use crate::{utils::format_duration, video::stream::MediaInfo};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum InfoFormat {
+    /// Print the streams as a human readable table.
+    #[default]
+    Table,
+
+    /// Print the streams as JSON.
+    Json,
+}
+
+impl fmt::Display for InfoFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InfoFormat::Table => write!(f, "table"),
+            InfoFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+const HEADERS: [&str; 8] = [
+    "#", "Type", "Codec", "Language", "Title", "Default", "Forced", "Duration",
+];
+
+fn get_rows(media_info: &MediaInfo) -> Vec<[String; 8]> {
+    let flag = |value: bool| if value { "yes" } else { "no" }.to_owned();
+    let optional = |value: Option<&str>| value.unwrap_or("-").to_owned();
+    media_info
+        .streams
+        .iter()
+        .map(|stream| {
+            [
+                stream.index.to_string(),
+                stream.kind.to_string(),
+                optional(stream.codec.as_deref()),
+                optional(stream.language.as_deref()),
+                optional(stream.title.as_deref()),
+                flag(stream.default),
+                flag(stream.forced),
+                stream
+                    .duration
+                    .or(media_info.duration)
+                    .map_or_else(|| "-".to_owned(), format_duration),
+            ]
+        })
+        .collect()
+}
+
+fn format_row<S>(row: &[S], widths: &[usize]) -> String
+where
+    S: AsRef<str>,
+{
+    row.iter()
+        .zip(widths)
+        .map(|(cell, width)| format!("{:<width$}", cell.as_ref(), width = width))
+        .collect::<Vec<_>>()
+        .join("  ")
+        .trim_end()
+        .to_owned()
+}
+
+fn print_table(media_info: &MediaInfo, term: &Term) -> Result<()> {
+    let rows = get_rows(media_info);
+    let mut widths = HEADERS.map(|header| header.chars().count());
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    term.write_line(&style(format_row(&HEADERS, &widths)).bold().to_string())?;
+    for row in rows.iter() {
+        term.write_line(&format_row(row, &widths))?;
+    }
+    Ok(())
+}
+
+pub fn print_media_info(media_info: &MediaInfo, format: InfoFormat) -> Result<()> {
+    let term = Term::stdout();
+    match format {
+        InfoFormat::Table => print_table(media_info, &term),
+        InfoFormat::Json => Ok(term.write_line(&serde_json::to_string_pretty(media_info)?)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video::stream::{StreamInfo, StreamKind};
+
+    #[test]
+    fn test_rows_fall_back_to_container_duration() {
+        let media_info = MediaInfo {
+            streams: vec![StreamInfo {
+                index: 0,
+                kind: StreamKind::Subtitle,
+                codec: Some("subrip".into()),
+                default: true,
+                ..Default::default()
+            }],
+            duration: Some(61.0),
+        };
+        assert_eq!(
+            get_rows(&media_info)[0],
+            [
+                "0",
+                "subtitle",
+                "subrip",
+                "-",
+                "-",
+                "yes",
+                "no",
+                "00:01:01.000"
+            ]
+            .map(String::from)
+        );
+    }
+
+    #[test]
+    fn test_format_row_pads_columns() {
+        assert_eq!(format_row(&["a", "bb", "c"], &[3, 3, 1]), "a    bb   c");
+    }
+}
@@ -0,0 +1,35 @@
+//! Seamlessly add subtitles to a video file.
+//!
+//! The [`Muxer`] runs the whole pipeline of the `subtrack` command: it fixes the encoding
+//! of the subtitles, decides the subtitle tracks of the output and runs ffmpeg, returning
+//! a [`MuxReport`] of what was written. Its progress can be followed with an [`EventSink`].
+//! The builders and enums it uses are exported for finer control.
+
+pub mod behavior;
+pub mod event;
+pub mod external;
+pub mod muxer;
+pub mod subtitle;
+pub mod video;
+
+// The pieces of the command line tool, not part of the library API
+#[doc(hidden)]
+pub mod batch;
+#[doc(hidden)]
+pub mod config;
+#[doc(hidden)]
+pub mod info;
+#[doc(hidden)]
+pub mod logger;
+#[doc(hidden)]
+pub mod utils;
+#[doc(hidden)]
+pub mod watch;
+
+pub use behavior::Behavior;
+pub use event::{ChannelSink, EventSink, MuxEvent, SilentSink};
+pub use muxer::{
+    plan::MuxStep, MuxOptions, MuxOutcome, MuxReport, MuxedSubtitle, Muxer, SubtitleSource,
+};
+pub use subtitle::{file::SubtitleFileBuilder, language::Language};
+pub use video::file::VideoFileBuilder;
@@ -1,121 +1,689 @@
-use std::time::Duration;
+pub mod settings;
+
+use std::{sync::Mutex, time::Duration};
 
 use anyhow::{Error, Result};
 use console::{style, Emoji, Term};
-use indicatif::ProgressBar;
+use indicatif::{MultiProgress, ProgressBar};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    batch::{BatchJob, BatchResult, BatchStatus},
+    event::{EventSink, MuxEvent},
+    external::ffmpeg::{get_shell_command, progress::FfmpegProgress},
+    muxer::plan::MuxStep,
+    subtitle::language::Language,
+    video::container::{StreamAction, StreamChange},
+};
+use settings::{LogFile, LogSettings, OutputFormat, Verbosity};
+
+enum LoggerOutput {
+    Term(Term),
+    Line { bar: ProgressBar, label: Box<str> },
+    Json { term: Term, video: Option<Box<str>> },
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a, E> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    video: Option<&'a str>,
+
+    #[serde(flatten)]
+    event: E,
+}
+
+impl LoggerOutput {
+    fn write_step(&self, line: &str) -> Result<()> {
+        match self {
+            LoggerOutput::Term(term) => Ok(term.write_line(line)?),
+            LoggerOutput::Line { bar, label } => {
+                bar.set_message(format!("{}: {}", label, line.trim()));
+                Ok(())
+            }
+            LoggerOutput::Json { .. } => Ok(()),
+        }
+    }
+
+    fn write_line(&self, line: &str) -> Result<()> {
+        match self {
+            LoggerOutput::Term(term) => Ok(term.write_line(line)?),
+            LoggerOutput::Line { bar, label } => {
+                bar.println(format!("{}: {}", label, line.trim()));
+                Ok(())
+            }
+            LoggerOutput::Json { .. } => Ok(()),
+        }
+    }
+
+    fn write_json<E>(&self, event: E) -> Result<Option<String>>
+    where
+        E: Serialize,
+    {
+        let LoggerOutput::Json { term, video } = self else {
+            return Ok(None);
+        };
+        let line = serde_json::to_string(&JsonLine {
+            video: video.as_deref(),
+            event,
+        })?;
+        term.write_line(&line)?;
+        Ok(Some(line))
+    }
+}
+
+fn get_json_result(result: &BatchResult) -> Value {
+    let mut value = json!({ "video": result.file_name });
+    match &result.status {
+        BatchStatus::Done => value["status"] = "done".into(),
+        BatchStatus::Skipped(reason) => {
+            value["status"] = "skipped".into();
+            value["reason"] = reason.as_ref().into();
+        }
+        BatchStatus::Failed(e) => {
+            value["status"] = "failed".into();
+            value["error"] = e.to_string().into();
+        }
+    }
+    value
+}
 
-use crate::subtitle::language::Language;
+fn format_batch_result(result: &BatchResult) -> String {
+    match &result.status {
+        BatchStatus::Done => format!(" {} {}", style(Emoji("✅", "OK")).green(), result.file_name),
+        BatchStatus::Skipped(reason) => format!(
+            " {} {} ({})",
+            style(Emoji("⏭️", "--")).yellow(),
+            result.file_name,
+            reason
+        ),
+        BatchStatus::Failed(e) => format!(
+            " {} {}: {}",
+            style(Emoji("✖️", "X")).red(),
+            result.file_name,
+            e
+        ),
+    }
+}
 
+/// Shows the events of the pipeline in the terminal.
 pub struct CLILogger {
-    term: Term,
-    total_steps: u8,
+    output: LoggerOutput,
+    verbosity: Verbosity,
+    log_file: Option<LogFile>,
+
+    steps: Mutex<Vec<MuxStep>>,
+
+    ffmpeg_bar: Mutex<Option<ProgressBar>>,
 }
 
-fn get_count_step(step: u8, total_steps: u8) -> String {
-    format!("[{}/{}]", step, total_steps)
+fn get_count_step(steps: &[MuxStep], step: MuxStep) -> String {
+    match steps.iter().position(|planned_step| *planned_step == step) {
+        Some(position) => format!("[{}/{}]", position + 1, steps.len()),
+        None => "[-]".to_owned(),
+    }
 }
 
 impl CLILogger {
-    pub fn new(subtitles_length: usize) -> Self {
+    pub fn new(settings: &LogSettings) -> Self {
+        let output = match settings.format {
+            OutputFormat::Text => LoggerOutput::Term(Term::stdout()),
+            OutputFormat::Json => LoggerOutput::Json {
+                term: Term::stdout(),
+                video: None,
+            },
+        };
         CLILogger {
-            term: Term::stdout(),
-            total_steps: (3 + subtitles_length) as u8,
+            output,
+            verbosity: settings.verbosity,
+            log_file: settings.log_file.clone(),
+            steps: Mutex::new(Vec::new()),
+            ffmpeg_bar: Mutex::new(None),
+        }
+    }
+
+    /// Tags the JSON events with the video they belong to, to tell apart the videos of a batch.
+    pub fn with_video<S>(self, video_file: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        let LoggerOutput::Json { term, .. } = self.output else {
+            return self;
+        };
+        CLILogger {
+            output: LoggerOutput::Json {
+                term,
+                video: Some(video_file.as_ref().into()),
+            },
+            ..self
+        }
+    }
+
+    /// Creates a logger that reports everything on its own line of the progress display.
+    /// Quiet runs have no progress to show, so they print the kept lines directly.
+    pub fn with_progress_line<S>(
+        settings: &LogSettings,
+        multi_progress: &MultiProgress,
+        label: S,
+    ) -> Result<Self>
+    where
+        S: AsRef<str>,
+    {
+        if settings.verbosity == Verbosity::Quiet {
+            return Ok(CLILogger::new(settings));
+        }
+        let bar = multi_progress.add(ProgressBar::new_spinner());
+        bar.set_style(
+            indicatif::ProgressStyle::default_spinner().template("{spinner:.green} {msg}")?,
+        );
+        bar.enable_steady_tick(Duration::from_millis(100));
+        Ok(CLILogger {
+            output: LoggerOutput::Line {
+                bar,
+                label: label.as_ref().into(),
+            },
+            verbosity: settings.verbosity,
+            log_file: settings.log_file.clone(),
+            steps: Mutex::new(Vec::new()),
+            ffmpeg_bar: Mutex::new(None),
+        })
+    }
+
+    fn write_log_file(&self, line: &str) -> Result<()> {
+        let Some(log_file) = &self.log_file else {
+            return Ok(());
+        };
+        match &self.output {
+            LoggerOutput::Line { label, .. } => {
+                log_file.write_line(format!("{}: {}", label, line.trim()))
+            }
+            _ => log_file.write_line(line),
+        }
+    }
+
+    fn write_step(&self, line: &str) -> Result<()> {
+        self.write_log_file(line)?;
+        if self.verbosity == Verbosity::Quiet {
+            return Ok(());
         }
+        self.output.write_step(line)
     }
 
-    fn get_count_step(&self, step: u8) -> String {
-        get_count_step(step, self.total_steps)
+    fn write_line(&self, line: &str) -> Result<()> {
+        self.write_log_file(line)?;
+        self.output.write_line(line)
     }
 
-    pub fn get_subtitle_logger(&self, step: u8) -> SubtitleLogger {
-        SubtitleLogger::new(self.total_steps, step)
+    fn write_detail(&self, line: &str) -> Result<()> {
+        self.write_log_file(line)?;
+        if self.verbosity < Verbosity::Verbose {
+            return Ok(());
+        }
+        self.output.write_line(line)
     }
 
-    pub fn report_video_building(&self) -> Result<()> {
-        let count_step = self.get_count_step(1);
+    fn write_json<E>(&self, event: E) -> Result<bool>
+    where
+        E: Serialize,
+    {
+        let Some(line) = self.output.write_json(event)? else {
+            return Ok(false);
+        };
+        if let Some(log_file) = &self.log_file {
+            log_file.write_line(line)?;
+        }
+        Ok(true)
+    }
+
+    fn get_count_step(&self, step: MuxStep) -> String {
+        get_count_step(&self.steps.lock().unwrap(), step)
+    }
+
+    fn report_video_building(&self) -> Result<()> {
+        let count_step = self.get_count_step(MuxStep::CheckInput);
         let emoji_with_count = format!("🎬 {}", count_step);
         let emoji = Emoji(&emoji_with_count, &count_step);
-        Ok(self.term.write_line(&format!(
-            "{} Building video file and subtitles...",
+        self.write_step(&format!("{} Building video file...", style(emoji).green(),))
+    }
+
+    fn report_video_probing(&self) -> Result<()> {
+        let count_step = self.get_count_step(MuxStep::Probe);
+        let emoji_with_count = format!("🔍 {}", count_step);
+        let emoji = Emoji(&emoji_with_count, &count_step);
+        self.write_step(&format!(
+            "{} Reading the streams of the video...",
+            style(emoji).green(),
+        ))
+    }
+
+    fn report_output_verifying(&self) -> Result<()> {
+        let count_step = self.get_count_step(MuxStep::Verify);
+        let emoji_with_count = format!("🔎 {}", count_step);
+        let emoji = Emoji(&emoji_with_count, &count_step);
+        self.write_step(&format!(
+            "{} Verifying the output video...",
             style(emoji).green(),
-        ))?)
+        ))
     }
 
-    pub fn report_output_file_parsing<O>(&self, output_file_name: O) -> Result<()>
+    fn report_output_file_parsing<O>(&self, output_file_name: O) -> Result<()>
     where
         O: AsRef<str>,
     {
-        let count_step = self.get_count_step(2);
+        let count_step = self.get_count_step(MuxStep::ResolveOutput);
         let emoji_with_count = format!("📖 {}", count_step);
         let emoji = Emoji(&emoji_with_count, &count_step);
-        Ok(self.term.write_line(&format!(
+        self.write_step(&format!(
             "{} Parsing output file {}...",
             style(emoji).green(),
             output_file_name.as_ref()
-        ))?)
+        ))
     }
 
-    pub fn report_ffmpeg_started(&self) -> Result<ProgressBar> {
-        let bar = ProgressBar::new_spinner();
-        bar.set_style(
-            indicatif::ProgressStyle::default_spinner().template("{spinner:.green}  {msg}")?,
-        );
-        let count_step = self.get_count_step(self.total_steps);
-        let message = format!("{} Running ffmpeg to add subtitles...", count_step);
-        bar.set_message(message);
-        bar.enable_steady_tick(Duration::from_millis(100));
+    fn create_ffmpeg_bar<M>(&self, message: M, duration: Option<f64>) -> Result<ProgressBar>
+    where
+        M: AsRef<str>,
+    {
+        // Track the progress in milliseconds of the output video when its length is known
+        let (length, style) = match duration {
+            Some(duration) if duration > 0.0 => (
+                Some((duration * 1000.0) as u64),
+                indicatif::ProgressStyle::default_bar()
+                    .template(
+                        "{spinner:.green}  {msg} [{bar:30.green}] {percent}% {prefix} (ETA {eta})",
+                    )?
+                    .progress_chars("=> "),
+            ),
+            _ => (
+                None,
+                indicatif::ProgressStyle::default_spinner()
+                    .template("{spinner:.green}  {msg} {prefix}")?,
+            ),
+        };
+        let count_step = self.get_count_step(MuxStep::Mux);
+        let message = format!("{} {}", count_step, message.as_ref());
+
+        // The line of the video becomes its ffmpeg bar when processing several at once
+        let bar = match &self.output {
+            LoggerOutput::Term(_) if self.verbosity == Verbosity::Quiet => ProgressBar::hidden(),
+            LoggerOutput::Term(_) => {
+                let bar = match length {
+                    Some(length) => ProgressBar::new(length),
+                    None => ProgressBar::new_spinner(),
+                };
+                bar.set_message(message);
+                bar.enable_steady_tick(Duration::from_millis(100));
+                bar
+            }
+            LoggerOutput::Line { bar, label } => {
+                if let Some(length) = length {
+                    bar.set_length(length);
+                }
+                bar.set_message(format!("{}: {}", label, message));
+                bar.clone()
+            }
+            LoggerOutput::Json { .. } => ProgressBar::hidden(),
+        };
+        bar.set_style(style);
         Ok(bar)
     }
 
-    pub fn finish_ffmpeg(&self, bar: &ProgressBar) -> Result<()> {
-        bar.finish();
+    fn report_ffmpeg_started(
+        &self,
+        args: &[String],
+        duration: Option<f64>,
+        burn_in: bool,
+    ) -> Result<()> {
+        self.write_detail(&format!(
+            "{} {}",
+            style("Running").dim(),
+            get_shell_command(args)
+        ))?;
+        let message = if burn_in {
+            "Running ffmpeg to burn in subtitles..."
+        } else {
+            "Running ffmpeg to add subtitles..."
+        };
+        let bar = self.create_ffmpeg_bar(message, duration)?;
+        *self.ffmpeg_bar.lock().unwrap() = Some(bar);
+        Ok(())
+    }
+
+    fn report_ffmpeg_progress(&self, progress: &FfmpegProgress) {
+        let ffmpeg_bar = self.ffmpeg_bar.lock().unwrap();
+        let Some(bar) = ffmpeg_bar.as_ref() else {
+            return;
+        };
+        bar.set_position(progress.out_time.as_millis() as u64);
+        if let Some(speed) = progress.speed {
+            bar.set_prefix(format!("{:.1}x", speed));
+        }
+    }
+
+    fn report_ffmpeg_log(&self, line: &str) -> Result<()> {
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        let message = format!("{} {}", style("ffmpeg:").dim(), line.trim_end());
+        self.write_log_file(&message)?;
+        let ffmpeg_bar = self.ffmpeg_bar.lock().unwrap();
+        match (&self.output, ffmpeg_bar.as_ref()) {
+            (LoggerOutput::Line { bar, label }, _) => {
+                bar.println(format!("{}: {}", label, message))
+            }
+            (_, Some(bar)) => bar.suspend(|| Term::stderr().write_line(&message))?,
+            _ => Term::stderr().write_line(&message)?,
+        }
+        Ok(())
+    }
+
+    fn report_encoding_conversion<F, C>(
+        &self,
+        file_name: F,
+        encoding: &str,
+        converted_file: C,
+    ) -> Result<()>
+    where
+        F: AsRef<str>,
+        C: AsRef<str>,
+    {
+        self.write_detail(&format!(
+            "{} Converted {} from {} to UTF-8 in {}",
+            style("Encoding").dim(),
+            file_name.as_ref(),
+            encoding,
+            converted_file.as_ref()
+        ))
+    }
+
+    fn finish_ffmpeg(&self) -> Result<()> {
+        // A video line stays alive until the whole video is done
+        let bar = self.ffmpeg_bar.lock().unwrap().take();
+        if let (Some(bar), LoggerOutput::Term(_)) = (bar, &self.output) {
+            bar.finish();
+        }
 
-        let count_step = self.get_count_step(self.total_steps);
+        let count_step = self.get_count_step(MuxStep::Mux);
         let emoji_with_count = format!("🚀 {}", count_step);
         let emoji = Emoji(&emoji_with_count, &count_step);
-        self.term.write_line(&format!(
+        self.write_step(&format!(
             "{} Finished processing video file!",
             style(emoji).green()
-        ))?;
+        ))
+    }
+
+    fn abandon_ffmpeg(&self) {
+        let bar = self.ffmpeg_bar.lock().unwrap().take();
+        if let (Some(bar), LoggerOutput::Term(_)) = (bar, &self.output) {
+            bar.abandon();
+        }
+    }
+
+    /// Leaves the final status on the line of the video when processing several at once.
+    pub fn finish_batch_video(&self, status: &BatchStatus) -> Result<()> {
+        let LoggerOutput::Line { bar, label } = &self.output else {
+            return Ok(());
+        };
+        let status = match status {
+            BatchStatus::Done => format!("{} done", style(Emoji("✅", "OK")).green()),
+            BatchStatus::Skipped(reason) => {
+                format!("{} skipped, {}", style(Emoji("⏭️", "--")).yellow(), reason)
+            }
+            BatchStatus::Failed(e) => format!("{} failed: {}", style(Emoji("✖️", "X")).red(), e),
+        };
+        bar.set_style(indicatif::ProgressStyle::default_spinner().template("  {msg}")?);
+        bar.finish_with_message(format!("{}: {}", label, status));
         Ok(())
     }
 
-    pub fn report_error(&self, e: Error) -> Result<()> {
-        let term = Term::stderr();
+    fn report_in_place_replacement<O, B>(
+        &self,
+        original_file_name: O,
+        backup_file_name: Option<B>,
+    ) -> Result<()>
+    where
+        O: AsRef<str>,
+        B: AsRef<str>,
+    {
+        let backup = backup_file_name
+            .map(|backup_file_name| {
+                format!(" The original was kept at {}.", backup_file_name.as_ref())
+            })
+            .unwrap_or_default();
+        self.write_line(&format!(
+            " {} Replaced {} with the modified video.{}",
+            style(Emoji("🔁", "~")).green(),
+            original_file_name.as_ref(),
+            backup
+        ))
+    }
+
+    fn report_streams_changed(&self, changes: &[StreamChange]) -> Result<()> {
+        let lines: Vec<String> = changes
+            .iter()
+            .map(|change| {
+                let codec = change.codec.as_deref().unwrap_or("unknown");
+                match change.action {
+                    StreamAction::Convert { target_codec } => format!(
+                        " {} Converting the {} stream {} from {} to {} for the output container.",
+                        style(Emoji("🔄", "~")).yellow(),
+                        change.kind,
+                        change.index,
+                        codec,
+                        target_codec
+                    ),
+                    StreamAction::Drop => format!(
+                        " {} Dropping the {} stream {} ({}), the output container can't store it.",
+                        style(Emoji("⚠️", "!")).yellow(),
+                        change.kind,
+                        change.index,
+                        codec
+                    ),
+                }
+            })
+            .collect();
+        self.write_line(&lines.join("\n"))
+    }
+
+    fn report_skipped_output(&self) -> Result<()> {
+        self.write_step(&format!(
+            " {} Output file already exists, skipping the video.",
+            style(Emoji("⏭️", ">")).yellow(),
+        ))
+    }
+
+    fn report_dry_run<F>(&self, args: &[String], fixed_subtitle_files: &[F]) -> Result<()>
+    where
+        F: AsRef<str>,
+    {
+        let count_step = self.get_count_step(MuxStep::Mux);
+        let emoji_with_count = format!("📝 {}", count_step);
+        let emoji = Emoji(&emoji_with_count, &count_step);
+        let mut lines = vec![
+            format!(
+                "{} Dry run, nothing was written. ffmpeg would run:",
+                style(emoji).green()
+            ),
+            "".to_owned(),
+            format!("  {}", get_shell_command(args)),
+        ];
+        if !fixed_subtitle_files.is_empty() {
+            lines.push("".to_owned());
+            lines.push("The following fixed subtitle files would be created:".to_owned());
+            for file_name in fixed_subtitle_files.iter() {
+                lines.push(format!("  {}", file_name.as_ref()));
+            }
+        }
+        self.write_line(&lines.join("\n"))
+    }
+
+    pub fn report_batch_pairing(&self, jobs: &[BatchJob]) -> Result<()> {
+        if let LoggerOutput::Json { .. } = self.output {
+            for job in jobs.iter() {
+                self.write_json(json!({
+                    "event": "batch_pairing",
+                    "video": job.video_file,
+                    "subtitles": job.subtitles.iter().map(|subtitle| json!({
+                        "file_name": subtitle.file_name,
+                        "language": subtitle.language,
+                    })).collect::<Vec<_>>(),
+                    "error": job.error.as_ref().map(|e| e.to_string()),
+                }))?;
+            }
+            return Ok(());
+        }
+        self.write_line(&format!("{}", style("Proposed pairing").bold()))?;
+        for job in jobs.iter() {
+            self.write_line(&format!(" {}", job.video_file))?;
+            if let Some(e) = &job.error {
+                self.write_line(&format!("   {} {}", style(Emoji("✖️", "X")).red(), e))?;
+            } else if job.subtitles.is_empty() {
+                self.write_line(&format!("   {}", style("no matching subtitles").yellow()))?;
+            }
+            for subtitle in job.subtitles.iter() {
+                let language = subtitle
+                    .language
+                    .as_ref()
+                    .map(|language| language.to_string())
+                    .unwrap_or_else(|| "unknown language".into());
+                self.write_line(&format!(
+                    "   {} {} ({})",
+                    style("+").green(),
+                    subtitle.file_name,
+                    language
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Asks whether to go on with the batch, which is refused when not run in a terminal.
+    pub fn confirm_batch_pairing(&self) -> Result<bool> {
+        let term = Term::stdout();
         term.write_line("")?;
-        term.write_line(&format!(" {} Failed: {}", style(Emoji("✖️", "X")).red(), e))?;
-        std::process::exit(1);
+        term.write_str("Proceed with this pairing? [y/N] ")?;
+        let answer = term.read_line()?;
+        Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
     }
-}
 
-pub struct SubtitleLogger {
-    step: u8,
-    total_steps: u8,
-    term: Term,
-}
+    pub fn report_batch_video<S>(&self, position: usize, total: usize, file_name: S) -> Result<()>
+    where
+        S: AsRef<str>,
+    {
+        // The logger of the video already tags the event with it
+        let event = json!({ "event": "batch_video", "position": position, "total": total });
+        if self.write_json(event)? {
+            return Ok(());
+        }
+        self.write_step("")?;
+        self.write_step(&format!(
+            "{} Video {} of {}: {}",
+            style(Emoji("📼", "#")).cyan(),
+            position,
+            total,
+            style(file_name.as_ref()).bold()
+        ))
+    }
+
+    pub fn report_batch_summary(&self, results: &[BatchResult]) -> Result<()> {
+        if let LoggerOutput::Json { .. } = self.output {
+            for result in results.iter() {
+                let mut event = get_json_result(result);
+                event["event"] = "batch_result".into();
+                self.write_json(event)?;
+            }
+            return Ok(());
+        }
+        self.write_line("")?;
+        self.write_line(&format!("{}", style("Summary").bold()))?;
+        for result in results.iter() {
+            self.write_line(&format_batch_result(result))?;
+        }
+        Ok(())
+    }
 
-impl SubtitleLogger {
-    fn new(total_steps: u8, step: u8) -> Self {
-        SubtitleLogger {
-            step,
-            total_steps,
-            term: Term::stdout(),
+    pub fn report_watch_started<D, O>(&self, directory: D, output_directory: O) -> Result<()>
+    where
+        D: AsRef<str>,
+        O: AsRef<str>,
+    {
+        let event = json!({
+            "event": "watch_started",
+            "directory": directory.as_ref(),
+            "output_directory": output_directory.as_ref(),
+        });
+        if self.write_json(event)? {
+            return Ok(());
+        }
+        self.write_line(&format!(
+            "{} Watching {} and moving the results to {}...",
+            style(Emoji("👀", "*")).cyan(),
+            directory.as_ref(),
+            output_directory.as_ref()
+        ))
+    }
+
+    pub fn report_watch_job_started<S>(&self, video_file_name: S) -> Result<()>
+    where
+        S: AsRef<str>,
+    {
+        let event = json!({ "event": "watch_job_started" });
+        if self.write_json(event)? {
+            return Ok(());
+        }
+        self.write_step("")?;
+        self.write_step(&format!(
+            "{} Processing {}",
+            style(Emoji("📼", "#")).cyan(),
+            style(video_file_name.as_ref()).bold()
+        ))
+    }
+
+    pub fn report_watch_job<S>(
+        &self,
+        result: &BatchResult,
+        result_file_name: Option<S>,
+    ) -> Result<()>
+    where
+        S: AsRef<str>,
+    {
+        if let LoggerOutput::Json { .. } = self.output {
+            let mut event = get_json_result(result);
+            event["event"] = "watch_job".into();
+            if let Some(result_file_name) = &result_file_name {
+                event["output_file"] = result_file_name.as_ref().into();
+            }
+            self.write_json(event)?;
+            return Ok(());
+        }
+        let line = format_batch_result(result);
+        match result_file_name {
+            Some(result_file_name) => {
+                self.write_line(&format!("{} -> {}", line, result_file_name.as_ref()))
+            }
+            None => self.write_line(&line),
         }
     }
 
-    fn get_count_step(&self) -> String {
-        get_count_step(self.step + 2, self.total_steps)
+    /// Reports an error the watcher goes on after, unlike `report_error`.
+    pub fn report_watch_error(&self, e: &Error) -> Result<()> {
+        let event = json!({ "event": "watch_error", "message": e.to_string() });
+        if self.write_json(event)? {
+            return Ok(());
+        }
+        self.write_line(&format!(" {} {}", style(Emoji("⚠️", "!")).yellow(), e))
     }
 
-    pub fn report_subtitle_parsing_done<S>(
+    fn report_subtitle_parsing_done<S>(
         &self,
+        index: usize,
         language: &Option<Language>,
         file_name: S,
     ) -> Result<()>
     where
         S: AsRef<str>,
     {
-        let count_step = self.get_count_step();
+        let count_step = self.get_count_step(MuxStep::Subtitle { index });
         let emoji_with_count = format!("✅ {}", count_step);
         let emoji = Emoji(&emoji_with_count, &count_step);
         let string = if let Some(language) = language {
@@ -123,10 +691,108 @@ impl SubtitleLogger {
         } else {
             file_name.as_ref().to_string()
         };
-        Ok(self.term.write_line(&format!(
+        self.write_step(&format!(
             "{} Subtitle file {} parsed successfully.",
             style(emoji).green(),
             string
-        ))?)
+        ))
+    }
+
+    /// Reports the error ending the command, which then exits with a non-zero code.
+    pub fn report_error(&self, e: Error) -> Result<()> {
+        if self.write_json(json!({ "event": "error", "message": e.to_string() }))? {
+            return Ok(());
+        }
+        let line = format!(" {} Failed: {}", style(Emoji("✖️", "X")).red(), e);
+        self.write_log_file(&line)?;
+        let term = Term::stderr();
+        term.write_line("")?;
+        term.write_line(&line)?;
+        Ok(())
+    }
+}
+
+impl EventSink for CLILogger {
+    fn send(&self, event: MuxEvent) -> Result<()> {
+        if self.write_json(&event)? {
+            return Ok(());
+        }
+        match event {
+            MuxEvent::Planned { steps } => {
+                *self.steps.lock().unwrap() = steps;
+                Ok(())
+            }
+            MuxEvent::VideoBuilding => self.report_video_building(),
+            MuxEvent::VideoProbing => self.report_video_probing(),
+            MuxEvent::OutputResolved { file_name } => self.report_output_file_parsing(file_name),
+            MuxEvent::OutputSkipped => self.report_skipped_output(),
+            MuxEvent::EncodingConverted {
+                file_name,
+                encoding,
+                converted_file,
+                ..
+            } => self.report_encoding_conversion(file_name, encoding, converted_file),
+            MuxEvent::SubtitleParsed {
+                index,
+                file_name,
+                language,
+            } => self.report_subtitle_parsing_done(index, &language, file_name),
+            MuxEvent::StreamsChanged { changes } => self.report_streams_changed(&changes),
+            MuxEvent::FfmpegStarted {
+                args,
+                duration,
+                burn_in,
+            } => self.report_ffmpeg_started(&args, duration, burn_in),
+            MuxEvent::FfmpegLog { line } => self.report_ffmpeg_log(&line),
+            MuxEvent::FfmpegProgress(progress) => {
+                self.report_ffmpeg_progress(&progress);
+                Ok(())
+            }
+            MuxEvent::FfmpegFinished => self.finish_ffmpeg(),
+            MuxEvent::DryRun {
+                args,
+                fixed_subtitle_files,
+            } => self.report_dry_run(&args, &fixed_subtitle_files),
+            MuxEvent::OutputVerifying => self.report_output_verifying(),
+            MuxEvent::InPlaceReplaced {
+                original_file,
+                backup_file,
+            } => self.report_in_place_replacement(original_file, backup_file),
+            MuxEvent::Finished { .. } => Ok(()),
+
+            // The error itself is reported by whoever handles it
+            MuxEvent::Failed { .. } => {
+                self.abandon_ffmpeg();
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_step_follows_the_plan() {
+        let steps = [
+            MuxStep::CheckInput,
+            MuxStep::Probe,
+            MuxStep::Subtitle { index: 0 },
+            MuxStep::Mux,
+            MuxStep::Verify,
+        ];
+        assert_eq!(get_count_step(&steps, MuxStep::Probe), "[2/5]");
+        assert_eq!(get_count_step(&steps, MuxStep::Verify), "[5/5]");
+        assert_eq!(get_count_step(&steps, MuxStep::ResolveOutput), "[-]");
+    }
+
+    #[test]
+    fn test_count_step_with_many_subtitles() {
+        let steps: Vec<MuxStep> = (0..300).map(|index| MuxStep::Subtitle { index }).collect();
+        assert_eq!(
+            get_count_step(&steps, MuxStep::Subtitle { index: 299 }),
+            "[300/300]"
+        );
     }
 }
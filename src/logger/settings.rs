@@ -0,0 +1,146 @@
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+
+use crate::external::ffmpeg::FfmpegLogLevel;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum OutputFormat {
+    /// Print the progress for people, with colors and progress bars.
+    #[default]
+    Text,
+
+    /// Print every event as a line of JSON.
+    Json,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::Text => write!(f, "text"),
+            OutputFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// How much is printed, chosen with -q, -v and -vv.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only the results and the errors.
+    Quiet,
+
+    /// The progress of every step.
+    #[default]
+    Normal,
+
+    /// Also the ffmpeg commands, the encoding conversions and the ffmpeg information.
+    Verbose,
+
+    /// Also the ffmpeg debug log.
+    VeryVerbose,
+}
+
+impl Verbosity {
+    pub fn new(quiet: bool, verbose: u8) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            (false, _) => Verbosity::VeryVerbose,
+        }
+    }
+
+    pub fn get_ffmpeg_log_level(&self) -> FfmpegLogLevel {
+        match self {
+            Verbosity::Quiet => FfmpegLogLevel::Fatal,
+            Verbosity::Normal => FfmpegLogLevel::Error,
+            Verbosity::Verbose => FfmpegLogLevel::Info,
+            Verbosity::VeryVerbose => FfmpegLogLevel::Debug,
+        }
+    }
+}
+
+/// A file receiving a plain copy of everything printed, shared by the loggers of a batch.
+#[derive(Clone)]
+pub struct LogFile {
+    file: Arc<Mutex<File>>,
+}
+
+impl LogFile {
+    /// Opens the file, appending to it so the log of previous runs is kept.
+    pub fn open<S>(file_name: S) -> Result<Self>
+    where
+        S: AsRef<str>,
+    {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file_name.as_ref())
+            .map_err(|e| anyhow!("Failed to open the log file {}: {}.", file_name.as_ref(), e))?;
+        Ok(LogFile {
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Writes the line without its colors.
+    pub fn write_line<S>(&self, line: S) -> Result<()>
+    where
+        S: AsRef<str>,
+    {
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{}", console::strip_ansi_codes(line.as_ref()))?;
+        Ok(())
+    }
+}
+
+/// How every logger of a run prints.
+#[derive(Clone, Default)]
+pub struct LogSettings {
+    pub format: OutputFormat,
+    pub verbosity: Verbosity,
+    pub log_file: Option<LogFile>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_verbosity_flags() {
+        assert_eq!(Verbosity::new(false, 0), Verbosity::Normal);
+        assert_eq!(Verbosity::new(true, 0), Verbosity::Quiet);
+        assert_eq!(Verbosity::new(false, 2), Verbosity::VeryVerbose);
+        assert_eq!(
+            Verbosity::new(false, 1).get_ffmpeg_log_level(),
+            FfmpegLogLevel::Info
+        );
+    }
+
+    #[test]
+    fn test_log_file_is_appended_without_colors() -> Result<()> {
+        let directory = TempDir::new()?;
+        let file_name = directory.path().join("subtrack.log");
+        let file_name = file_name.to_str().unwrap();
+        fs::write(file_name, "previous run\n")?;
+
+        let log_file = LogFile::open(file_name)?;
+        log_file.write_line(
+            console::style("done")
+                .green()
+                .force_styling(true)
+                .to_string(),
+        )?;
+        assert_eq!(fs::read_to_string(file_name)?, "previous run\ndone\n");
+        Ok(())
+    }
+}
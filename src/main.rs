@@ -1,95 +1,565 @@
-mod behavior;
-mod external;
-mod logger;
-mod subtitle;
-mod utils;
-mod video;
-
-use anyhow::{anyhow, Result};
-use behavior::Behavior;
-use clap::{command, Parser};
-use external::ffmpeg::add_subtitles_to_video;
-use logger::CLILogger;
-use subtitle::{file::SubtitleFileBuilder, handling::SubtitleHandling};
-use utils::parse_output_file;
-use video::{file::VideoFileBuilder, format::VideoFormat};
+use std::{
+    env, fs,
+    num::NonZeroUsize,
+    path::Path,
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Error, Result};
+use clap::{ArgAction, CommandFactory, FromArgMatches, Parser, Subcommand};
+use indicatif::MultiProgress;
+use notify::{RecursiveMode, Watcher};
+use subtrack::{
+    batch::{plan_batch_jobs, BatchJob, BatchResult, BatchStatus},
+    config::{get_profile_argument, Config, ConfigSettings},
+    external::ffprobe,
+    info::{print_media_info, InfoFormat},
+    logger::{
+        settings::{LogFile, LogSettings, OutputFormat, Verbosity},
+        CLILogger,
+    },
+    subtitle::{discovery::discover_subtitles, language::Language},
+    watch::{get_result_file_names, WatchSession, WatchState},
+    MuxOptions, MuxOutcome, Muxer, SubtitleSource, VideoFileBuilder,
+};
 
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// The video file to add the subtitles
-    input_file: Box<str>,
+    #[arg(required = true)]
+    input_file: Option<Box<str>>,
 
     /// The name of the output video file
-    #[arg(short, long)]
+    #[arg(
+        short,
+        long,
+        conflicts_with_all = ["in_place", "output_suffix", "output_template", "output_directory"]
+    )]
     output_file: Option<Box<str>>,
 
-    /// How subtitles are added to the video container
-    #[arg(short, long, default_value_t)]
-    behavior: Behavior,
+    /// The subtitle file and language separated by a comma, optionally followed by the
+    /// default, forced and sdh flags and a title=<TITLE> for the track, with its commas
+    /// written as \,. When omitted, the subtitles named after the video are used.
+    #[arg(short, long = "subtitle", value_name = "SUBTITLE,LANGUAGE[,FLAGS]")]
+    subtitles: Vec<Box<str>>,
 
-    /// Changes the way how the created subtitle files are handled
-    #[arg(short, long, default_value_t)]
-    temp_subtitle_handling: SubtitleHandling,
+    #[command(flatten)]
+    options: MuxOptions,
 
-    /// The subtitle file and language separated by a comma.
-    #[arg(short, long = "subtitle", value_name = "SUBTITLE,LANGUAGE")]
-    subtitles: Vec<Box<str>>,
+    /// How the progress is printed, json prints one event per line for scripts
+    #[arg(long, global = true, default_value_t)]
+    output_format: OutputFormat,
+
+    /// Only print the results and the errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print the ffmpeg commands and the ffmpeg log, -vv also prints its debug log
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Append everything printed, including the ffmpeg log, to the file
+    #[arg(long, global = true, value_name = "FILE")]
+    log_file: Option<Box<str>>,
+
+    /// Use the defaults of a profile of the configuration files
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<Box<str>>,
 }
 
-fn run_cli(args: Cli, logger: &CLILogger) -> Result<()> {
-    if args.subtitles.is_empty() {
-        return Err(anyhow!("At least one subtitle file must be specified."));
-    }
+#[derive(Subcommand)]
+enum Command {
+    /// List the streams of a video file
+    Info {
+        /// The video file to inspect
+        input_file: Box<str>,
+
+        /// How the streams are printed
+        #[arg(short, long, default_value_t)]
+        format: InfoFormat,
+    },
+
+    /// Add the subtitles found next to every video of a directory tree
+    Batch {
+        /// The directory containing the videos
+        directory: Box<str>,
+
+        /// Process the videos without confirming the proposed subtitle pairing
+        #[arg(short, long)]
+        yes: bool,
+
+        /// How many videos are processed at the same time
+        #[arg(short, long, default_value = "1")]
+        jobs: NonZeroUsize,
+
+        #[command(flatten)]
+        options: MuxOptions,
+    },
+
+    /// Add the subtitles to the videos dropped in a directory as soon as they are complete
+    Watch {
+        /// The directory receiving the videos and subtitles, the resulting videos are moved
+        /// to the output directory
+        directory: Box<str>,
+
+        /// How many seconds the files must stop changing before processing them
+        #[arg(long, default_value_t = 10)]
+        settle_seconds: u64,
+
+        /// The file remembering the processed videos, defaults to .subtrack-watch.json
+        /// in the watched directory
+        #[arg(long)]
+        state_file: Option<Box<str>>,
+
+        #[command(flatten)]
+        options: MuxOptions,
+    },
+}
 
-    // Obtain current subtitle stream of video
-    logger.report_video_building()?;
+fn run_info<S>(input_file: S, format: InfoFormat) -> Result<()>
+where
+    S: AsRef<str>,
+{
     let video_file = VideoFileBuilder::new()
-        .with_input_file(args.input_file)?
+        .with_input_file(input_file)?
         .build()?;
+    let media_info = ffprobe::probe(video_file.get_file_name())?;
+    print_media_info(&media_info, format)
+}
+
+fn get_batch_status(outcome: Result<MuxOutcome>) -> BatchStatus {
+    match outcome {
+        Ok(MuxOutcome::Muxed(_)) => BatchStatus::Done,
+        Ok(MuxOutcome::Skipped) => BatchStatus::Skipped("output file already exists".into()),
+        Err(e) => BatchStatus::Failed(e),
+    }
+}
+
+fn run_cli(args: Cli, logger: &CLILogger) -> Result<()> {
+    let input_file = args
+        .input_file
+        .ok_or_else(|| anyhow!("An input video file must be specified."))?;
+    if !args.subtitles.is_empty() {
+        let subtitle_sources: Vec<SubtitleSource> = args
+            .subtitles
+            .into_iter()
+            .map(SubtitleSource::Option)
+            .collect();
+        Muxer::new(args.options).mux_with_sink(
+            input_file,
+            args.output_file,
+            &subtitle_sources,
+            logger,
+        )?;
+        return Ok(());
+    }
 
-    // Get output file
-    let output_file = parse_output_file(args.output_file, &video_file.file_name, &logger)?;
-    if args.subtitles.len() > 1 && !output_file.supports_multiple_subtitle_streams() {
+    // Look for subtitles named after the video when none are given, once the video is known
+    // to exist so a missing one isn't reported as missing subtitles
+    VideoFileBuilder::new().with_input_file(&input_file)?;
+    let subtitle_sources: Vec<SubtitleSource> = discover_subtitles(Path::new(input_file.as_ref()))?
+        .into_iter()
+        .map(SubtitleSource::Discovered)
+        .collect();
+    if subtitle_sources.is_empty() {
         return Err(anyhow!(
-            "Video file with format {:?} does not support multiple subtitle streams.",
-            video_file.format
+            "No subtitle file was given or found next to the video. Please add one with --subtitle."
         ));
     }
+    Muxer::new(args.options).mux_with_sink(
+        input_file,
+        args.output_file,
+        &subtitle_sources,
+        logger,
+    )?;
+    Ok(())
+}
 
-    // Parse subtitles and languages
-    let mut subtitles = Vec::with_capacity(args.subtitles.len());
-    for (i, subtitle_option) in args.subtitles.iter().enumerate() {
-        subtitles.push(
-            SubtitleFileBuilder::new()
-                .with_subtitle_logger(logger.get_subtitle_logger(i as u8 + 1))
-                .with_subtitle_option(subtitle_option, args.temp_subtitle_handling)?
-                .build()?,
-        );
-    }
+fn run_batch_job(
+    job: &BatchJob,
+    output_file: Option<Box<str>>,
+    muxer: &Muxer,
+    logger: &CLILogger,
+) -> BatchResult {
+    let subtitle_sources: Vec<SubtitleSource> = job
+        .subtitles
+        .iter()
+        .cloned()
+        .map(SubtitleSource::Discovered)
+        .collect();
 
-    // If MP4 always overwrite as it won't support more than 1 subtitle
-    let behavior = if output_file.format == VideoFormat::MP4 {
-        Behavior::Overwrite
+    // Keep going with the rest of the videos when one of them fails
+    let status = if let Some(e) = &job.error {
+        BatchStatus::Failed(anyhow!("{}", e))
+    } else if subtitle_sources.is_empty() {
+        BatchStatus::Skipped("no matching subtitles found".into())
     } else {
-        args.behavior
+        get_batch_status(muxer.mux_with_sink(
+            &job.video_file,
+            output_file,
+            &subtitle_sources,
+            logger,
+        ))
     };
+    BatchResult {
+        file_name: job.video_file.clone(),
+        status,
+    }
+}
+
+fn run_batch<S>(
+    directory: S,
+    yes: bool,
+    jobs: NonZeroUsize,
+    options: MuxOptions,
+    settings: &LogSettings,
+) -> Result<()>
+where
+    S: AsRef<str>,
+{
+    // The question would be mixed with the events read by scripts
+    let needs_confirmation = !yes && !options.dry_run;
+    if needs_confirmation && settings.format == OutputFormat::Json {
+        return Err(anyhow!(
+            "The pairing can't be confirmed with JSON output. Use --yes to accept it."
+        ));
+    }
+    let batch_jobs = plan_batch_jobs(directory, &options)?;
+    let batch_logger = CLILogger::new(settings);
+    batch_logger.report_batch_pairing(&batch_jobs)?;
+    if needs_confirmation && !batch_logger.confirm_batch_pairing()? {
+        return Err(anyhow!(
+            "The pairing was not confirmed. Use --yes to accept it without asking."
+        ));
+    }
 
-    // Run ffmpeg command to add subtitles
-    add_subtitles_to_video(&video_file, subtitles, &output_file, &behavior, &logger)?;
+    let muxer = Muxer::new(options);
+    let total = batch_jobs.len();
+    let mut results = Vec::with_capacity(total);
+    if jobs.get() == 1 {
+        for (i, batch_job) in batch_jobs.iter().enumerate() {
+            let logger = CLILogger::new(settings).with_video(&batch_job.video_file);
+            logger.report_batch_video(i + 1, total, &batch_job.video_file)?;
+            results.push(run_batch_job(batch_job, None, &muxer, &logger));
+        }
+    } else {
+        // Workers take the next pending video until none are left, each on its own line
+        let multi_progress = MultiProgress::new();
+        let next_job = AtomicUsize::new(0);
+        let finished_jobs = Mutex::new(Vec::with_capacity(total));
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..jobs.get().min(total))
+                .map(|_| {
+                    scope.spawn(|| -> Result<()> {
+                        while let Some(batch_job) =
+                            batch_jobs.get(next_job.fetch_add(1, Ordering::SeqCst))
+                        {
+                            let label = Path::new(batch_job.video_file.as_ref())
+                                .file_name()
+                                .unwrap_or_default()
+                                .to_string_lossy();
+                            let logger = match settings.format {
+                                OutputFormat::Text => {
+                                    CLILogger::with_progress_line(settings, &multi_progress, label)?
+                                }
+                                OutputFormat::Json => {
+                                    CLILogger::new(settings).with_video(&batch_job.video_file)
+                                }
+                            };
+                            let result = run_batch_job(batch_job, None, &muxer, &logger);
+                            logger.finish_batch_video(&result.status)?;
+                            finished_jobs.lock().unwrap().push(result);
+                        }
+                        Ok(())
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .try_for_each(|worker| worker.join().unwrap())
+        })?;
+
+        // Report in the same order as the videos were listed
+        let mut finished_jobs = finished_jobs.into_inner().unwrap();
+        finished_jobs.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+        results = finished_jobs;
+    }
+
+    batch_logger.report_batch_summary(&results)?;
+    let failed = results.iter().filter(|result| result.is_failed()).count();
+    if failed > 0 {
+        return Err(anyhow!("{} of {} videos failed.", failed, results.len()));
+    }
     Ok(())
 }
 
+fn run_watch_job<S>(
+    job: &BatchJob,
+    output_directory: S,
+    session: &WatchSession,
+    muxer: &Muxer,
+    logger: &CLILogger,
+) -> (BatchResult, Option<Box<str>>)
+where
+    S: AsRef<str>,
+{
+    try_run_watch_job(job, output_directory, session, muxer, logger).unwrap_or_else(|e| {
+        let result = BatchResult {
+            file_name: job.video_file.clone(),
+            status: BatchStatus::Failed(e),
+        };
+        (result, None)
+    })
+}
+
+fn try_run_watch_job<S>(
+    job: &BatchJob,
+    output_directory: S,
+    session: &WatchSession,
+    muxer: &Muxer,
+    logger: &CLILogger,
+) -> Result<(BatchResult, Option<Box<str>>)>
+where
+    S: AsRef<str>,
+{
+    let options = muxer.get_options();
+    let languages: Vec<Language> = job
+        .subtitles
+        .iter()
+        .filter_map(|subtitle| subtitle.language.clone())
+        .collect();
+    let (partial_file_name, result_file_name) = get_result_file_names(
+        &job.video_file,
+        output_directory,
+        &options.get_output_template(),
+        &languages,
+    )?;
+    session.check_result_file(&result_file_name)?;
+    if !options.dry_run {
+        if let Some(result_directory) = Path::new(partial_file_name.as_ref()).parent() {
+            fs::create_dir_all(result_directory)?;
+        }
+    }
+    let Some(result_file_name) = options.on_conflict.resolve(result_file_name)? else {
+        let result = BatchResult {
+            file_name: job.video_file.clone(),
+            status: BatchStatus::Skipped("output file already exists".into()),
+        };
+        return Ok((result, None));
+    };
+
+    // Leftovers of an interrupted run are never complete
+    if Path::new(partial_file_name.as_ref()).exists() {
+        fs::remove_file(partial_file_name.as_ref())?;
+    }
+    let result = run_batch_job(job, Some(partial_file_name.clone()), muxer, logger);
+    if options.dry_run {
+        return Ok((result, Some(result_file_name)));
+    }
+    match result.status {
+        BatchStatus::Done => {
+            fs::rename(partial_file_name.as_ref(), result_file_name.as_ref())?;
+            Ok((result, Some(result_file_name)))
+        }
+        _ => {
+            if Path::new(partial_file_name.as_ref()).exists() {
+                fs::remove_file(partial_file_name.as_ref())?;
+            }
+            Ok((result, None))
+        }
+    }
+}
+
+fn run_watch<D>(
+    directory: D,
+    settle_time: Duration,
+    state_file: Option<Box<str>>,
+    options: MuxOptions,
+    settings: &LogSettings,
+) -> Result<()>
+where
+    D: AsRef<str>,
+{
+    if options.in_place {
+        return Err(anyhow!(
+            "Videos can't be modified in place when watching a directory."
+        ));
+    }
+    let Some(output_directory) = options.output_directory.clone() else {
+        return Err(anyhow!(
+            "An output directory must be given with --output-directory when watching."
+        ));
+    };
+    fs::create_dir_all(output_directory.as_ref())?;
+    // The results must not be picked up again when written inside the watched directory
+    let output_path = fs::canonicalize(output_directory.as_ref())?;
+    let state_file = state_file.unwrap_or_else(|| {
+        Path::new(directory.as_ref())
+            .join(".subtrack-watch.json")
+            .to_string_lossy()
+            .into()
+    });
+    let state = WatchState::load(&state_file)?;
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(Path::new(directory.as_ref()), RecursiveMode::Recursive)?;
+    // Dry runs process the videos again on every start
+    let state_file = (!options.dry_run).then_some(state_file);
+    let watched_path = fs::canonicalize(directory.as_ref())?;
+    let mut session = WatchSession::new(state, state_file, settle_time, watched_path, output_path);
+    let muxer = Muxer::new(options);
+    let watch_logger = CLILogger::new(settings);
+    watch_logger.report_watch_started(&directory, &output_directory)?;
+
+    // Scan at start for the files that arrived while not running. Errors, even failing to
+    // print them, never stop the watcher
+    let mut needs_scan = true;
+    loop {
+        let event = match receiver.recv_timeout(Duration::from_secs(1)) {
+            Ok(event) => Some(event),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(anyhow!("Stopped receiving file system notifications."))
+            }
+        };
+        for event in event.into_iter().chain(receiver.try_iter()) {
+            match event {
+                Ok(_) => needs_scan = true,
+                Err(e) => {
+                    let _ = watch_logger.report_watch_error(&e.into());
+                }
+            }
+        }
+        if !needs_scan && !session.is_waiting() {
+            continue;
+        }
+        needs_scan = false;
+
+        let jobs = match plan_batch_jobs(&directory, muxer.get_options()) {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                let _ = watch_logger.report_watch_error(&e);
+                continue;
+            }
+        };
+        for (job, signature) in session.get_ready_jobs(jobs, Instant::now()) {
+            let logger = CLILogger::new(settings).with_video(&job.video_file);
+            let _ = logger.report_watch_job_started(&job.video_file);
+            let (result, result_file_name) =
+                run_watch_job(&job, &output_directory, &session, &muxer, &logger);
+            let _ = watch_logger.report_watch_job(&result, result_file_name);
+            if let Err(e) = session.finish_job(&job.video_file, signature, (&result.status).into())
+            {
+                let _ = watch_logger.report_watch_error(&e);
+            }
+        }
+    }
+}
+
+fn parse_args() -> Result<(Cli, ConfigSettings)> {
+    let args: Vec<_> = env::args_os().collect();
+    let profile_args: Vec<_> = args.iter().map(|arg| arg.to_string_lossy()).collect();
+    let config_settings = Config::load()?.get_settings(get_profile_argument(&profile_args))?;
+    let matches = config_settings
+        .apply_defaults(Cli::command(), &args)?
+        .get_matches_from(args);
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    Ok((cli, config_settings))
+}
+
+fn complete_options(
+    options: MuxOptions,
+    verbosity: Verbosity,
+    config_settings: &ConfigSettings,
+) -> MuxOptions {
+    MuxOptions {
+        ffmpeg_log_level: verbosity.get_ffmpeg_log_level(),
+        language_encoders: config_settings.language_encoders.clone(),
+        ..options
+    }
+}
+
+fn exit_with_error(logger: &CLILogger, e: Error) -> ! {
+    // Nothing else can be done when printing the error fails
+    let _ = logger.report_error(e);
+    process::exit(1);
+}
+
 fn main() -> Result<()> {
-    let args = Cli::parse();
-    let logger = CLILogger::new(args.subtitles.len());
+    let (mut args, config_settings) = match parse_args() {
+        Ok(parsed) => parsed,
+        Err(e) => exit_with_error(&CLILogger::new(&LogSettings::default()), e),
+    };
+    let verbosity = Verbosity::new(args.quiet, args.verbose);
+    let mut settings = LogSettings {
+        format: args.output_format,
+        verbosity,
+        log_file: None,
+    };
+    match args.log_file.as_ref().map(LogFile::open).transpose() {
+        Ok(log_file) => settings.log_file = log_file,
+        Err(e) => exit_with_error(&CLILogger::new(&settings), e),
+    }
+    let logger = CLILogger::new(&settings);
+    let logger = match &args.input_file {
+        Some(input_file) => logger.with_video(input_file),
+        None => logger,
+    };
 
     // Run CLI
-    let cli_result = run_cli(args, &logger);
+    let cli_result = match args.command.take() {
+        Some(Command::Info { input_file, format }) => run_info(input_file, format),
+        Some(Command::Batch {
+            directory,
+            yes,
+            jobs,
+            options,
+        }) => {
+            let options = complete_options(options, verbosity, &config_settings);
+            run_batch(directory, yes, jobs, options, &settings)
+        }
+        Some(Command::Watch {
+            directory,
+            settle_seconds,
+            state_file,
+            options,
+        }) => {
+            let options = complete_options(options, verbosity, &config_settings);
+            run_watch(
+                directory,
+                Duration::from_secs(settle_seconds),
+                state_file,
+                options,
+                &settings,
+            )
+        }
+        None => {
+            args.options = complete_options(args.options, verbosity, &config_settings);
+            run_cli(args, &logger)
+        }
+    };
 
     // Pretty print errors
     if let Err(e) = cli_result {
-        logger.report_error(e)?;
+        exit_with_error(&logger, e);
     }
 
     Ok(())
@@ -0,0 +1,662 @@
+pub mod plan;
+
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+use crate::{
+    behavior::Behavior,
+    event::{EventSink, MuxEvent, SilentSink},
+    external::{
+        ffmpeg::{
+            get_args_for_adding_subtitles, get_args_for_burning_subtitle, run_ffmpeg_with_events,
+            FfmpegLogLevel,
+        },
+        ffprobe,
+    },
+    subtitle::{
+        discovery::DiscoveredSubtitle,
+        disposition::Disposition,
+        file::{SubtitleFile, SubtitleFileBuilder, SubtitleOption},
+        handling::SubtitleHandling,
+        language::{Language, LanguageEncoders},
+        order::SubtitleOrder,
+        selector::SubtitleSelector,
+    },
+    utils::parse_output_file,
+    video::{
+        collision::CollisionPolicy,
+        container::{ContainerConversion, StreamChange},
+        encoder::VideoEncoder,
+        file::VideoFileBuilder,
+        format::VideoFormat,
+        in_place::InPlaceOutput,
+        naming::OutputTemplate,
+        verification::OutputExpectation,
+    },
+};
+use plan::plan_steps;
+
+/// Options shared by every video processed, alone or in a batch.
+#[derive(Args, Clone, Debug, PartialEq)]
+pub struct MuxOptions {
+    /// What to do when the output video file already exists
+    #[arg(long, default_value_t, conflicts_with = "in_place")]
+    pub on_conflict: CollisionPolicy,
+
+    /// The suffix added to the input file name when no output file is given
+    #[arg(long, default_value = "-subs")]
+    pub output_suffix: Box<str>,
+
+    /// The output file name when no output file is given, with the {stem}, {ext}, {langs},
+    /// {dir} and {date} placeholders
+    #[arg(long, value_name = "TEMPLATE", conflicts_with_all = ["output_suffix", "in_place"])]
+    pub output_template: Option<OutputTemplate>,
+
+    /// The directory of the output videos when no output file is given, created if missing
+    #[arg(short = 'd', long, conflicts_with = "in_place")]
+    pub output_directory: Option<Box<str>>,
+
+    /// Modify the input video, replacing it once the new one is verified
+    #[arg(long)]
+    pub in_place: bool,
+
+    /// Keep a copy of the original video with the .bak suffix when modifying it in place
+    #[arg(long, requires = "in_place")]
+    pub backup: bool,
+
+    /// How subtitles are added to the video container
+    #[arg(short, long, default_value_t)]
+    pub behavior: Behavior,
+
+    /// Changes the way how the created subtitle files are handled
+    #[arg(short, long, default_value_t)]
+    pub temp_subtitle_handling: SubtitleHandling,
+
+    /// The index or language of a pre-existing subtitle to remove with the remove behavior.
+    #[arg(short, long = "remove-subtitle", value_name = "INDEX|LANGUAGE")]
+    pub removed_subtitles: Vec<SubtitleSelector>,
+
+    /// The order of the subtitle tracks: new-first, existing-first, language or a comma
+    /// separated list of stream indices and new:<N> for the N-th new subtitle.
+    #[arg(long, value_name = "ORDER")]
+    pub subtitle_order: Option<SubtitleOrder>,
+
+    /// Burn the subtitle into the video frames instead of adding a subtitle track
+    #[arg(long)]
+    pub burn_in: bool,
+
+    #[command(flatten)]
+    pub encoder: VideoEncoder,
+
+    /// Print the ffmpeg command and the fixed subtitle files without writing anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// How much ffmpeg writes to its log, set from the verbosity of the command line
+    #[arg(skip)]
+    pub ffmpeg_log_level: FfmpegLogLevel,
+
+    /// The encoders tried for the subtitles of some languages, set from the configuration
+    #[arg(skip)]
+    pub language_encoders: LanguageEncoders,
+}
+
+impl Default for MuxOptions {
+    fn default() -> Self {
+        MuxOptions {
+            on_conflict: CollisionPolicy::default(),
+            output_suffix: "-subs".into(),
+            output_template: None,
+            output_directory: None,
+            in_place: false,
+            backup: false,
+            behavior: Behavior::default(),
+            temp_subtitle_handling: SubtitleHandling::default(),
+            removed_subtitles: Vec::new(),
+            subtitle_order: None,
+            burn_in: false,
+            encoder: VideoEncoder::default(),
+            dry_run: false,
+            ffmpeg_log_level: FfmpegLogLevel::default(),
+            language_encoders: LanguageEncoders::new(),
+        }
+    }
+}
+
+impl MuxOptions {
+    /// The template naming the output videos, built from the suffix when none is given.
+    pub fn get_output_template(&self) -> OutputTemplate {
+        self.output_template
+            .clone()
+            .unwrap_or_else(|| OutputTemplate::from_suffix(&self.output_suffix))
+    }
+}
+
+/// Where a subtitle added to a video comes from.
+#[derive(Clone, Debug)]
+pub enum SubtitleSource {
+    /// A subtitle given like the --subtitle option, `file[,language][,flags]`.
+    Option(Box<str>),
+
+    /// A subtitle found next to the video.
+    Discovered(DiscoveredSubtitle),
+}
+
+impl SubtitleSource {
+    /// The language of the subtitle, when it's given or found in its file name.
+    pub fn get_language(&self) -> Result<Option<Language>> {
+        match self {
+            SubtitleSource::Option(subtitle_option) => {
+                Ok(SubtitleOption::parse(subtitle_option)?.language)
+            }
+            SubtitleSource::Discovered(subtitle) => Ok(subtitle.language.clone()),
+        }
+    }
+}
+
+/// A subtitle track added to the output video.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MuxedSubtitle {
+    /// The file given to ffmpeg, which is the fixed UTF-8 copy when it was converted.
+    pub file_name: Box<str>,
+    pub language: Option<Language>,
+    pub disposition: Disposition,
+    pub title: Option<Box<str>>,
+
+    /// Whether the file was converted to UTF-8 or cleaned before adding it.
+    pub is_transformed: bool,
+}
+
+impl From<&SubtitleFile> for MuxedSubtitle {
+    fn from(subtitle: &SubtitleFile) -> Self {
+        MuxedSubtitle {
+            file_name: subtitle.file_name.clone(),
+            language: subtitle.language.clone(),
+            disposition: subtitle.disposition,
+            title: subtitle.title.clone(),
+            is_transformed: !subtitle.is_original_subtitle_file,
+        }
+    }
+}
+
+/// What was written when the subtitles were added to a video.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MuxReport {
+    /// The resulting video, which is the input video when it was modified in place.
+    pub output_file: Box<str>,
+
+    /// The copy of the original video kept when modifying it in place.
+    pub backup_file: Option<Box<str>>,
+
+    /// The new subtitles in the order they were given.
+    pub subtitles: Vec<MuxedSubtitle>,
+
+    /// The streams of the input converted or dropped to fit in the output container.
+    pub stream_changes: Vec<StreamChange>,
+
+    /// The arguments ffmpeg ran with, or would run with on a dry run.
+    pub ffmpeg_args: Vec<String>,
+
+    /// Whether nothing was written as the options asked for a dry run.
+    pub dry_run: bool,
+}
+
+/// How adding the subtitles to a video ended.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MuxOutcome {
+    /// The subtitles were added to the video.
+    Muxed(MuxReport),
+
+    /// The output video already exists and the collision policy skips it.
+    Skipped,
+}
+
+/// Adds subtitles to videos with the same options, the entry point of the library.
+///
+/// ```no_run
+/// use subtrack::{MuxOptions, MuxOutcome, Muxer, SubtitleSource};
+///
+/// let muxer = Muxer::new(MuxOptions::default());
+/// let subtitles = [SubtitleSource::Option("movie.en.srt,english".into())];
+/// if let MuxOutcome::Muxed(report) = muxer.mux("movie.mkv", None, &subtitles)? {
+///     println!("Written to {}", report.output_file);
+/// }
+/// # anyhow::Ok(())
+/// ```
+pub struct Muxer {
+    options: MuxOptions,
+}
+
+impl Muxer {
+    pub fn new(options: MuxOptions) -> Self {
+        Muxer { options }
+    }
+
+    pub fn get_options(&self) -> &MuxOptions {
+        &self.options
+    }
+
+    /// Adds the subtitles to the video without printing anything. The output defaults to
+    /// the input file name with the output suffix, or the output template.
+    pub fn mux<S>(
+        &self,
+        input_file: S,
+        output_file: Option<Box<str>>,
+        subtitle_sources: &[SubtitleSource],
+    ) -> Result<MuxOutcome>
+    where
+        S: AsRef<str>,
+    {
+        self.mux_with_sink(input_file, output_file, subtitle_sources, &SilentSink)
+    }
+
+    /// Adds the subtitles to the video, sending every step to the event sink.
+    pub fn mux_with_sink<S>(
+        &self,
+        input_file: S,
+        output_file: Option<Box<str>>,
+        subtitle_sources: &[SubtitleSource],
+        event_sink: &dyn EventSink,
+    ) -> Result<MuxOutcome>
+    where
+        S: AsRef<str>,
+    {
+        let outcome = self.run(input_file, output_file, subtitle_sources, event_sink);
+        match &outcome {
+            Ok(MuxOutcome::Muxed(report)) => event_sink.send(MuxEvent::Finished {
+                output_file: report.output_file.clone(),
+            })?,
+            Ok(MuxOutcome::Skipped) => {}
+            Err(e) => event_sink.send(MuxEvent::Failed {
+                error: e.to_string().into(),
+            })?,
+        }
+        outcome
+    }
+
+    fn run<S>(
+        &self,
+        input_file: S,
+        output_file: Option<Box<str>>,
+        subtitle_sources: &[SubtitleSource],
+        event_sink: &dyn EventSink,
+    ) -> Result<MuxOutcome>
+    where
+        S: AsRef<str>,
+    {
+        let options = &self.options;
+        if subtitle_sources.is_empty() {
+            return Err(anyhow!("At least one subtitle file must be specified."));
+        }
+        if options.burn_in && subtitle_sources.len() > 1 {
+            return Err(anyhow!(
+                "Only one subtitle file can be burned into the video."
+            ));
+        }
+        if options.behavior != Behavior::Remove && !options.removed_subtitles.is_empty() {
+            return Err(anyhow!(
+                "Subtitles can only be selected for removal with the remove behavior."
+            ));
+        }
+
+        // Announce every step first so their progress can be counted
+        event_sink.send(MuxEvent::Planned {
+            steps: plan_steps(options, subtitle_sources.len()),
+        })?;
+
+        // Obtain current subtitle stream of video
+        event_sink.send(MuxEvent::VideoBuilding)?;
+        let video_file = VideoFileBuilder::new()
+            .with_input_file(input_file)?
+            .build()?;
+
+        // Get output file, which is a temporary file next to the input when modifying it in place
+        let in_place_output = if options.in_place {
+            Some(InPlaceOutput::new(&video_file, options.backup)?)
+        } else {
+            None
+        };
+        let output_file = match &in_place_output {
+            Some(in_place_output) => VideoFileBuilder::new()
+                .with_output_file(
+                    in_place_output.get_temporary_file_name(),
+                    CollisionPolicy::Error,
+                )?
+                .build()?,
+            None => {
+                let output_file = match output_file {
+                    Some(output_file) => output_file,
+                    None => {
+                        let languages = subtitle_sources
+                            .iter()
+                            .filter_map(|source| source.get_language().transpose())
+                            .collect::<Result<Vec<Language>>>()?;
+                        let output_file = options.get_output_template().render(
+                            &video_file.file_name,
+                            options.output_directory.as_ref(),
+                            &languages,
+                        )?;
+
+                        // The template may name directories that don't exist yet
+                        let output_directory = Path::new(output_file.as_ref()).parent();
+                        if let Some(output_directory) = output_directory {
+                            if !options.dry_run && !output_directory.as_os_str().is_empty() {
+                                fs::create_dir_all(output_directory)?;
+                            }
+                        }
+                        output_file
+                    }
+                };
+                let output_file = parse_output_file(
+                    output_file,
+                    &video_file.file_name,
+                    options.on_conflict,
+                    event_sink,
+                )?;
+                let Some(output_file) = output_file else {
+                    event_sink.send(MuxEvent::OutputSkipped)?;
+                    return Ok(MuxOutcome::Skipped);
+                };
+                output_file
+            }
+        };
+        // MP4 only holds the new subtitle, so the pre-existing tracks are always overwritten
+        let behavior = match (&output_file.format, options.behavior) {
+            (VideoFormat::MP4, behavior @ (Behavior::Remove | Behavior::Replace)) => {
+                return Err(anyhow!(
+                    "The {} behavior can't be used with an MP4 output, which only keeps the new subtitle. Please use an MKV output or the overwrite behavior.",
+                    behavior
+                ));
+            }
+            (VideoFormat::MP4, _) => Behavior::Overwrite,
+            (_, behavior) => behavior,
+        };
+
+        event_sink.send(MuxEvent::VideoProbing)?;
+        let media_info = ffprobe::probe(video_file.get_file_name())?;
+        if subtitle_sources.len() > 1 && !output_file.supports_multiple_subtitle_streams() {
+            return Err(anyhow!(
+                "Video file with format {:?} does not support multiple subtitle streams.",
+                video_file.format
+            ));
+        }
+
+        // Parse subtitles and languages
+        let mut subtitles = Vec::with_capacity(subtitle_sources.len());
+        for (i, subtitle_source) in subtitle_sources.iter().enumerate() {
+            let builder = SubtitleFileBuilder::new()
+                .with_event_sink(event_sink, i)
+                .with_dry_run(options.dry_run)
+                .with_language_encoders(&options.language_encoders);
+            let builder =
+                match subtitle_source {
+                    SubtitleSource::Option(subtitle_option) => builder
+                        .with_subtitle_option(subtitle_option, options.temp_subtitle_handling)?,
+                    SubtitleSource::Discovered(subtitle) => builder
+                        .with_discovered_subtitle(subtitle, options.temp_subtitle_handling)?,
+                };
+            subtitles.push(builder.build()?);
+        }
+
+        // Decide the subtitle tracks of the output and their order
+        let tracks = behavior.get_subtitle_tracks(
+            &subtitles,
+            &media_info,
+            &options.removed_subtitles,
+            options.subtitle_order.as_ref(),
+        )?;
+
+        // Check every stream fits in the output container before running ffmpeg
+        let mut conversion = ContainerConversion::new(
+            &media_info,
+            &tracks,
+            &video_file.format,
+            &output_file.format,
+            options.burn_in,
+        )?;
+        if behavior != options.behavior {
+            conversion.drop_subtitle_streams(&media_info);
+        }
+        let tracks = conversion.filter_tracks(&tracks);
+        if !conversion.changes.is_empty() {
+            event_sink.send(MuxEvent::StreamsChanged {
+                changes: conversion.changes.clone(),
+            })?;
+        }
+        let mut report = MuxReport {
+            output_file: output_file.file_name.clone(),
+            backup_file: None,
+            subtitles: subtitles.iter().map(MuxedSubtitle::from).collect(),
+            stream_changes: conversion.changes.clone(),
+            ffmpeg_args: Vec::new(),
+            dry_run: options.dry_run,
+        };
+
+        report.ffmpeg_args = if options.burn_in {
+            get_args_for_burning_subtitle(
+                &video_file,
+                &subtitles[0],
+                &tracks,
+                &output_file,
+                &options.encoder,
+                &conversion,
+                options.ffmpeg_log_level,
+            )
+        } else {
+            get_args_for_adding_subtitles(
+                &video_file,
+                &subtitles,
+                &tracks,
+                &output_file,
+                &conversion,
+                options.ffmpeg_log_level,
+            )?
+        };
+
+        // Show what would be run without touching any file
+        if options.dry_run {
+            let fixed_subtitle_files = subtitles
+                .iter()
+                .filter(|sub| !sub.is_original_subtitle_file)
+                .map(|sub| sub.file_name.clone())
+                .collect();
+            event_sink.send(MuxEvent::DryRun {
+                args: report.ffmpeg_args.clone(),
+                fixed_subtitle_files,
+            })?;
+            return Ok(MuxOutcome::Muxed(report));
+        }
+
+        // Describe the output before ffmpeg, the fixed subtitle files are ready by now
+        let expectation = OutputExpectation::new(
+            &subtitles,
+            &tracks,
+            &conversion,
+            &output_file.format,
+            &media_info,
+            options.burn_in,
+        )?;
+
+        // Run ffmpeg command to burn or add subtitles
+        run_ffmpeg_with_events(
+            &report.ffmpeg_args,
+            media_info.duration,
+            options.burn_in,
+            event_sink,
+        )?;
+
+        // Check every subtitle made it before keeping the output
+        event_sink.send(MuxEvent::OutputVerifying)?;
+        let verification = ffprobe::probe_with_packet_counts(output_file.get_file_name())
+            .and_then(|output_info| expectation.verify(&output_info));
+        discard_unverified_output(
+            verification,
+            output_file.get_file_name(),
+            in_place_output.is_some(),
+        )?;
+
+        // Swap the original video with the verified output
+        if let Some(in_place_output) = in_place_output {
+            let original_file_name: Box<str> = in_place_output.get_original_file_name().into();
+            let backup_file_name = in_place_output.replace()?;
+            event_sink.send(MuxEvent::InPlaceReplaced {
+                original_file: original_file_name.clone(),
+                backup_file: backup_file_name.clone(),
+            })?;
+            report.output_file = original_file_name;
+            report.backup_file = backup_file_name;
+        }
+        Ok(MuxOutcome::Muxed(report))
+    }
+}
+
+fn discard_unverified_output<S>(
+    verification: Result<()>,
+    output_file_name: S,
+    is_in_place: bool,
+) -> Result<()>
+where
+    S: AsRef<str>,
+{
+    let Err(e) = verification else {
+        return Ok(());
+    };
+    if is_in_place {
+        return Err(anyhow!("{} The original file was left untouched.", e));
+    }
+    if Path::new(output_file_name.as_ref()).exists() {
+        fs::remove_file(output_file_name.as_ref())?;
+    }
+    Err(anyhow!("{} The output video was deleted.", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::mpsc};
+
+    use clap::Parser;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::event::ChannelSink;
+
+    #[derive(Parser)]
+    struct TestCli {
+        #[command(flatten)]
+        options: MuxOptions,
+    }
+
+    #[test]
+    fn test_default_options_match_the_command_line() {
+        assert_eq!(
+            TestCli::parse_from(["subtrack"]).options,
+            MuxOptions::default()
+        );
+    }
+
+    #[test]
+    fn test_existing_output_is_skipped() -> Result<()> {
+        let directory = TempDir::new()?;
+        let video_file = directory.path().join("video.mkv");
+        let subtitle_file = directory.path().join("video.srt");
+        fs::write(&video_file, b"")?;
+        fs::write(&subtitle_file, b"")?;
+        fs::write(directory.path().join("video-subs.mkv"), b"")?;
+
+        let muxer = Muxer::new(MuxOptions {
+            on_conflict: CollisionPolicy::Skip,
+            ..Default::default()
+        });
+        let subtitles = [SubtitleSource::Option(
+            subtitle_file.to_str().unwrap().into(),
+        )];
+        let (sender, receiver) = mpsc::channel();
+        let outcome = muxer.mux_with_sink(
+            video_file.to_str().unwrap(),
+            None,
+            &subtitles,
+            &ChannelSink::new(sender),
+        )?;
+        assert_eq!(outcome, MuxOutcome::Skipped);
+
+        // The plan is announced before the steps, even those never reached
+        let events: Vec<MuxEvent> = receiver.try_iter().collect();
+        assert_eq!(
+            events[0],
+            MuxEvent::Planned {
+                steps: plan_steps(muxer.get_options(), 1)
+            }
+        );
+        assert_eq!(events.last(), Some(&MuxEvent::OutputSkipped));
+        Ok(())
+    }
+
+    #[test]
+    fn test_mp4_output_rejects_remove_and_replace() -> Result<()> {
+        let directory = TempDir::new()?;
+        let video_file = directory.path().join("video.mkv");
+        let subtitle_file = directory.path().join("video.srt");
+        fs::write(&video_file, b"")?;
+        fs::write(&subtitle_file, b"")?;
+        let subtitles = [SubtitleSource::Option(
+            subtitle_file.to_str().unwrap().into(),
+        )];
+        let output_file = directory.path().join("video.mp4");
+
+        for behavior in [Behavior::Remove, Behavior::Replace] {
+            let muxer = Muxer::new(MuxOptions {
+                behavior,
+                ..Default::default()
+            });
+            let e = muxer
+                .mux(
+                    video_file.to_str().unwrap(),
+                    Some(output_file.to_str().unwrap().into()),
+                    &subtitles,
+                )
+                .unwrap_err();
+            assert!(e.to_string().starts_with(&format!(
+                "The {} behavior can't be used with an MP4 output",
+                behavior
+            )));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_unverified_output_is_deleted() -> Result<()> {
+        let directory = TempDir::new()?;
+        let output_file = directory.path().join("video-subs.mkv");
+        let output_file_name = output_file.to_str().unwrap();
+        fs::write(&output_file, b"")?;
+
+        discard_unverified_output(Ok(()), output_file_name, false)?;
+        assert!(output_file.exists());
+
+        let failure = || {
+            Err(anyhow!(
+                "The output video has 1 subtitle streams instead of 2."
+            ))
+        };
+        let e = discard_unverified_output(failure(), output_file_name, true).unwrap_err();
+        assert!(e
+            .to_string()
+            .ends_with("The original file was left untouched."));
+        assert!(output_file.exists());
+
+        let e = discard_unverified_output(failure(), output_file_name, false).unwrap_err();
+        assert!(e.to_string().ends_with("The output video was deleted."));
+        assert!(!output_file.exists());
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_mux_without_subtitles() {
+        Muxer::new(MuxOptions::default())
+            .mux("video.mkv", None, &[])
+            .unwrap();
+    }
+}
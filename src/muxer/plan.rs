@@ -0,0 +1,99 @@
+use serde::Serialize;
+
+use crate::muxer::MuxOptions;
+
+/// A step of adding subtitles to a video, announced before running so its progress can be
+/// counted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum MuxStep {
+    /// Checking the input video exists and has a supported format.
+    CheckInput,
+
+    /// Choosing the output video, absent when modifying the input in place.
+    ResolveOutput,
+
+    /// Reading the streams of the input video with ffprobe.
+    Probe,
+
+    /// Parsing, converting to UTF-8 and validating the subtitle at the given position.
+    Subtitle { index: usize },
+
+    /// Running ffmpeg, or printing its command on a dry run.
+    Mux,
+
+    /// Probing the output video to check every subtitle made it, before it replaces the
+    /// input when modifying it in place.
+    Verify,
+}
+
+/// Lists the steps the muxer goes through with the options, in order.
+pub fn plan_steps(options: &MuxOptions, subtitles_length: usize) -> Vec<MuxStep> {
+    let mut steps = vec![MuxStep::CheckInput];
+    if !options.in_place {
+        steps.push(MuxStep::ResolveOutput);
+    }
+    steps.push(MuxStep::Probe);
+    steps.extend((0..subtitles_length).map(|index| MuxStep::Subtitle { index }));
+    steps.push(MuxStep::Mux);
+    if !options.dry_run {
+        steps.push(MuxStep::Verify);
+    }
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_for_a_new_output() {
+        assert_eq!(
+            plan_steps(&MuxOptions::default(), 2),
+            [
+                MuxStep::CheckInput,
+                MuxStep::ResolveOutput,
+                MuxStep::Probe,
+                MuxStep::Subtitle { index: 0 },
+                MuxStep::Subtitle { index: 1 },
+                MuxStep::Mux,
+                MuxStep::Verify,
+            ]
+        );
+
+        let options = MuxOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        assert_eq!(plan_steps(&options, 2).last(), Some(&MuxStep::Mux));
+    }
+
+    #[test]
+    fn test_plan_in_place() {
+        let options = MuxOptions {
+            in_place: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            plan_steps(&options, 1),
+            [
+                MuxStep::CheckInput,
+                MuxStep::Probe,
+                MuxStep::Subtitle { index: 0 },
+                MuxStep::Mux,
+                MuxStep::Verify,
+            ]
+        );
+
+        let options = MuxOptions {
+            dry_run: true,
+            ..options
+        };
+        assert_eq!(plan_steps(&options, 1).last(), Some(&MuxStep::Mux));
+    }
+
+    #[test]
+    fn test_plan_with_many_subtitles() {
+        assert_eq!(plan_steps(&MuxOptions::default(), 300).len(), 305);
+    }
+}
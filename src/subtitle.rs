@@ -1,5 +1,10 @@
+pub mod discovery;
+pub mod disposition;
 pub mod encoding;
 pub mod file;
 pub mod format;
 pub mod handling;
 pub mod language;
+pub mod matching;
+pub mod order;
+pub mod selector;
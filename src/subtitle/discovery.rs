@@ -0,0 +1,218 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+
+use super::{disposition::Disposition, format::SubtitleFormat, language::Language};
+use crate::utils::get_file_stem;
+
+/// A subtitle found next to a video by following the file naming convention.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveredSubtitle {
+    pub file_name: Box<str>,
+    pub language: Option<Language>,
+    pub disposition: Disposition,
+}
+
+impl DiscoveredSubtitle {
+    /// Whether both would be muxed as the same track, with the same language and disposition.
+    pub fn is_same_track(&self, other: &DiscoveredSubtitle) -> bool {
+        self.language == other.language && self.disposition == other.disposition
+    }
+}
+
+fn parse_language<S>(language: S) -> Option<Language>
+where
+    S: AsRef<str>,
+{
+    Language::new(language.as_ref())
+        .ok()
+        .or_else(|| Language::from_metadata_tag(language))
+}
+
+/// Splits the trailing language and forced or SDH flags from the stem of a subtitle file,
+/// like `episode 1.en.forced`.
+pub fn split_subtitle_tags(stem: &str) -> (&str, Option<Language>, Disposition) {
+    let mut base = stem;
+    let mut disposition = Disposition::default();
+    while let Some((rest, tag)) = base.rsplit_once('.') {
+        match tag.to_lowercase().as_str() {
+            flag @ ("forced" | "sdh") => match disposition.with_flag(flag) {
+                Ok(flagged_disposition) => disposition = flagged_disposition,
+                Err(_) => break,
+            },
+            _ => break,
+        }
+        base = rest;
+    }
+    let language = base
+        .rsplit_once('.')
+        .and_then(|(rest, tag)| Some((rest, parse_language(tag)?)));
+    match language {
+        Some((rest, language)) => (rest, Some(language), disposition),
+        None => (base, None, disposition),
+    }
+}
+
+fn parse_subtitle_file_name(file: &Path, video_stem: &str) -> Option<DiscoveredSubtitle> {
+    SubtitleFormat::new(file).ok()?;
+    let stem = get_file_stem(file).ok()?;
+    let (language, disposition) = if stem.as_ref() == video_stem {
+        (None, Disposition::default())
+    } else {
+        match split_subtitle_tags(&stem) {
+            (base, Some(language), disposition) if base == video_stem => {
+                (Some(language), disposition)
+            }
+            _ => return None,
+        }
+    };
+    Some(DiscoveredSubtitle {
+        file_name: file.to_str()?.into(),
+        language,
+        disposition,
+    })
+}
+
+fn read_directory(directory: &Path) -> Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(directory).map_err(|e| {
+        anyhow!(
+            "Failed to read the directory {}: {}.",
+            directory.display(),
+            e
+        )
+    })?;
+    entries
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<_>>()
+}
+
+/// Finds the subtitles whose names follow the file name of the video, looking next to it
+/// and in the `Subs/` folder of its directory.
+pub fn discover_subtitles(video_file: &Path) -> Result<Vec<DiscoveredSubtitle>> {
+    let video_stem = get_file_stem(video_file)?;
+    let directory = match video_file.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+
+    let mut files = read_directory(directory)?;
+    let subtitle_directories: Vec<PathBuf> = files
+        .iter()
+        .filter(|file| {
+            file.is_dir()
+                && file
+                    .file_name()
+                    .is_some_and(|name| name.eq_ignore_ascii_case("subs"))
+        })
+        .cloned()
+        .collect();
+    for subtitle_directory in subtitle_directories.iter() {
+        files.extend(read_directory(subtitle_directory)?);
+    }
+
+    let mut subtitles: Vec<DiscoveredSubtitle> = files
+        .iter()
+        .filter(|file| file.is_file())
+        .filter_map(|file| parse_subtitle_file_name(file, &video_stem))
+        .collect();
+    subtitles.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+    Ok(subtitles)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn create_files(directory: &TempDir, file_names: &[&str]) -> Result<()> {
+        for file_name in file_names {
+            fs::write(directory.path().join(file_name), b"")?;
+        }
+        Ok(())
+    }
+
+    fn get_found_subtitles(subtitles: Vec<DiscoveredSubtitle>) -> Vec<(String, Option<Language>)> {
+        subtitles
+            .into_iter()
+            .map(|subtitle| {
+                let file_name = Path::new(subtitle.file_name.as_ref())
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned();
+                (file_name, subtitle.language)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_discover_subtitles_by_file_name() -> Result<()> {
+        let directory = TempDir::new()?;
+        create_files(
+            &directory,
+            &[
+                "episode 1.mkv",
+                "episode 1.srt",
+                "episode 1.en.srt",
+                "episode 1.spanish.srt",
+                "episode 1.unknown.srt",
+                "episode 10.srt",
+                "episode 1.txt",
+            ],
+        )?;
+
+        let subtitles = discover_subtitles(&directory.path().join("episode 1.mkv"))?;
+        assert_eq!(
+            get_found_subtitles(subtitles),
+            vec![
+                ("episode 1.en.srt".to_owned(), Some(Language::English)),
+                ("episode 1.spanish.srt".to_owned(), Some(Language::Spanish)),
+                ("episode 1.srt".to_owned(), None),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_discover_subtitles_with_flags_in_subs_folder() -> Result<()> {
+        let directory = TempDir::new()?;
+        fs::create_dir(directory.path().join("Subs"))?;
+        create_files(
+            &directory,
+            &[
+                "episode 1.mkv",
+                "Subs/episode 1.eng.forced.srt",
+                "Subs/episode 1.fr.sdh.srt",
+                "Subs/episode 1.en.commentary.srt",
+            ],
+        )?;
+
+        let subtitles = discover_subtitles(&directory.path().join("episode 1.mkv"))?;
+        let dispositions: Vec<Disposition> = subtitles
+            .iter()
+            .map(|subtitle| subtitle.disposition)
+            .collect();
+        assert_eq!(
+            get_found_subtitles(subtitles),
+            vec![
+                (
+                    "episode 1.eng.forced.srt".to_owned(),
+                    Some(Language::English)
+                ),
+                ("episode 1.fr.sdh.srt".to_owned(), Some(Language::French)),
+            ]
+        );
+        assert_eq!(
+            dispositions,
+            vec![
+                Disposition::default().with_flag("forced")?,
+                Disposition::default().with_flag("sdh")?,
+            ]
+        );
+        Ok(())
+    }
+}
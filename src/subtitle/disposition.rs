@@ -0,0 +1,84 @@
+use anyhow::{anyhow, Result};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Disposition {
+    /// The player should select this track when no preference is set.
+    pub default: bool,
+
+    /// The track should be displayed even if subtitles are turned off.
+    pub forced: bool,
+
+    /// The track describes sounds and speakers for the deaf and hard of hearing (SDH).
+    pub hearing_impaired: bool,
+}
+
+impl Disposition {
+    /// Sets the flag named as in the `--subtitle` option.
+    pub fn with_flag<S>(self, flag: S) -> Result<Self>
+    where
+        S: AsRef<str>,
+    {
+        match flag.as_ref().to_lowercase().as_str() {
+            "default" => Ok(Disposition {
+                default: true,
+                ..self
+            }),
+            "forced" => Ok(Disposition {
+                forced: true,
+                ..self
+            }),
+            "sdh" => Ok(Disposition {
+                hearing_impaired: true,
+                ..self
+            }),
+            flag => Err(anyhow!("Subtitle flag {} not supported.", flag)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.default && !self.forced && !self.hearing_impaired
+    }
+
+    pub fn to_ffmpeg_value(self) -> Box<str> {
+        let flags: Vec<&str> = [
+            (self.default, "default"),
+            (self.forced, "forced"),
+            (self.hearing_impaired, "hearing_impaired"),
+        ]
+        .into_iter()
+        .filter_map(|(is_set, flag)| is_set.then_some(flag))
+        .collect();
+        if flags.is_empty() {
+            "0".into()
+        } else {
+            flags.join("+").into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffmpeg_value() -> Result<()> {
+        let disposition = Disposition::default();
+        assert_eq!(disposition.to_ffmpeg_value().as_ref(), "0");
+        let disposition = disposition.with_flag("forced")?;
+        assert_eq!(disposition.to_ffmpeg_value().as_ref(), "forced");
+        let disposition = disposition.with_flag("Default")?;
+        assert_eq!(disposition.to_ffmpeg_value().as_ref(), "default+forced");
+        let disposition = disposition.with_flag("SDH")?;
+        assert_eq!(
+            disposition.to_ffmpeg_value().as_ref(),
+            "default+forced+hearing_impaired"
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_unknown_flag() {
+        Disposition::default().with_flag("commentary").unwrap();
+    }
+}
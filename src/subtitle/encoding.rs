@@ -10,13 +10,22 @@ use encoding_rs_io::DecodeReaderBytesBuilder;
 use super::format::SubtitleFormat;
 use super::handling::SubtitleHandling;
 
+/// Finds an encoding by one of its labels, like windows-1252 or latin1.
+pub fn get_encoder<S>(label: S) -> Result<&'static Encoding>
+where
+    S: AsRef<str>,
+{
+    Encoding::for_label(label.as_ref().trim().as_bytes())
+        .ok_or_else(|| anyhow!("Unknown encoding {}.", label.as_ref()))
+}
+
 fn get_file_buffer(
     path: &Path,
     preferred_encoders: Option<Box<[&'static Encoding]>>,
-) -> Result<(String, bool)> {
+) -> Result<(String, &'static Encoding)> {
     let utf8_base_read = read_file(path, None);
     if let Ok(utf8_buffer) = utf8_base_read {
-        return Ok((utf8_buffer, false));
+        return Ok((utf8_buffer, encoding_rs::UTF_8));
     }
 
     // Try reading the file with other encodings
@@ -29,7 +38,7 @@ fn get_file_buffer(
     for encoder in preferred_encoders.unwrap().iter() {
         let encoded_read = read_file(path, Some(encoder));
         if let Ok(buffer) = encoded_read {
-            return Ok((buffer, true));
+            return Ok((buffer, encoder));
         }
     }
     Err(anyhow!(
@@ -49,47 +58,120 @@ fn read_file(path: &Path, encoding: Option<&'static Encoding>) -> Result<String>
     if utf8_read.is_ok() {
         return Ok(utf8_buffer);
     }
-    return Err(anyhow!(
+    Err(anyhow!(
         "Failed to encode the file {} to UTF-8 encoding.",
         path.display()
-    ));
+    ))
 }
 
-fn has_dos_line_endings(buffer: &String) -> bool {
+fn has_dos_line_endings(buffer: &str) -> bool {
     buffer.contains("\r\n")
 }
 
-fn dos_to_unix_line_endings(buffer: &String) -> String {
+fn dos_to_unix_line_endings(buffer: &str) -> String {
     buffer.replace('\r', "")
 }
 
-pub fn get_file_with_utf8_encoding(
+fn decode_file(
     file: &Path,
-    format: &SubtitleFormat,
     preferred_encoders: Option<Box<[&'static Encoding]>>,
-    handling: &SubtitleHandling,
-) -> Result<(Box<str>, bool)> {
-    let (file_buffer, is_transformed) = get_file_buffer(file, preferred_encoders)?;
+) -> Result<Option<(String, &'static Encoding)>> {
+    let (file_buffer, encoding) = get_file_buffer(file, preferred_encoders)?;
     let has_dos_line_endings = has_dos_line_endings(&file_buffer);
 
     // If the file is already UTF-8 encoded and does not have DOS line endings, return it as is
-    if !is_transformed && !has_dos_line_endings {
+    if encoding == encoding_rs::UTF_8 && !has_dos_line_endings {
+        return Ok(None);
+    }
+
+    let mut decoded_buffer = file_buffer;
+    if has_dos_line_endings {
+        decoded_buffer = dos_to_unix_line_endings(&decoded_buffer);
+    }
+    Ok(Some((decoded_buffer, encoding)))
+}
+
+/// Decodes the file into UTF-8 with Unix line endings in memory,
+/// returning None when the file can already be used as is.
+pub fn get_utf8_content(
+    file: &Path,
+    preferred_encoders: Option<Box<[&'static Encoding]>>,
+) -> Result<Option<String>> {
+    Ok(decode_file(file, preferred_encoders)?.map(|(content, _)| content))
+}
+
+/// Gets a UTF-8 version of the subtitle, along with the encoding it was decoded from when
+/// a new file had to be written.
+pub fn get_file_with_utf8_encoding(
+    file: &Path,
+    format: &SubtitleFormat,
+    preferred_encoders: Option<Box<[&'static Encoding]>>,
+    handling: &SubtitleHandling,
+    dry_run: bool,
+) -> Result<(Box<str>, Option<&'static Encoding>)> {
+    let Some((decoded_buffer, encoding)) = decode_file(file, preferred_encoders)? else {
         let file_name = file.to_str().ok_or_else(|| {
             anyhow!(
                 "The file {} is not valid UTF-8. Please rename the file.",
                 file.display()
             )
         })?;
-        return Ok((file_name.into(), false));
-    }
+        return Ok((file_name.into(), None));
+    };
 
-    let mut decoded_buffer = file_buffer;
-    if has_dos_line_endings {
-        decoded_buffer = dos_to_unix_line_endings(&decoded_buffer);
+    // Only report the name of the fixed file without creating it
+    let new_file_name = handling.get_file_name(file, format.to_extension())?;
+    if dry_run {
+        return Ok((new_file_name, Some(encoding)));
     }
 
-    let new_file_name = handling.get_file_name(file, format.to_extension())?;
     let mut file_buffer = File::create(new_file_name.as_ref())?;
     file_buffer.write_all(decoded_buffer.as_bytes())?;
-    Ok((new_file_name.into(), true))
+    Ok((new_file_name, Some(encoding)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::Builder;
+
+    use super::*;
+
+    #[test]
+    fn test_utf8_content_is_not_transformed() -> Result<()> {
+        let mut subtitle = Builder::new().suffix(".srt").tempfile()?;
+        subtitle.write_all("1\n00:00:01,000 --> 00:00:02,000\nCanción\n".as_bytes())?;
+        assert_eq!(get_utf8_content(subtitle.path(), None)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_latin1_content_with_dos_line_endings_is_transformed() -> Result<()> {
+        let mut subtitle = Builder::new().suffix(".srt").tempfile()?;
+        subtitle.write_all(b"1\r\n00:00:01,000 --> 00:00:02,000\r\nCanci\xf3n\r\n")?;
+        let encoders = Some(Box::from([encoding_rs::WINDOWS_1252]));
+        assert_eq!(
+            get_utf8_content(subtitle.path(), encoders)?.as_deref(),
+            Some("1\n00:00:01,000 --> 00:00:02,000\nCanción\n")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_dry_run_does_not_write_fixed_file() -> Result<()> {
+        let mut subtitle = Builder::new().suffix(".srt").tempfile()?;
+        subtitle.write_all(b"1\r\n00:00:01,000 --> 00:00:02,000\r\nHello\r\n")?;
+        let (file_name, encoding) = get_file_with_utf8_encoding(
+            subtitle.path(),
+            &SubtitleFormat::Srt,
+            None,
+            &SubtitleHandling::Keep,
+            true,
+        )?;
+        assert_eq!(encoding, Some(encoding_rs::UTF_8));
+        assert!(file_name.ends_with("-fixed.srt"));
+        assert!(!Path::new(file_name.as_ref()).exists());
+        Ok(())
+    }
 }
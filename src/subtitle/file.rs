@@ -1,33 +1,114 @@
-use std::path::Path;
+use std::{fs, path::Path};
 
 use anyhow::{anyhow, Result};
 
 use super::{
-    encoding::get_file_with_utf8_encoding, format::SubtitleFormat, handling::SubtitleHandling,
-    language::Language,
+    discovery::DiscoveredSubtitle, disposition::Disposition, encoding::get_file_with_utf8_encoding,
+    format::SubtitleFormat, handling::SubtitleHandling, language::Language,
+    language::LanguageEncoders,
 };
-use crate::logger::SubtitleLogger;
+use crate::event::{EventSink, MuxEvent};
+
+/// The parts of a subtitle given like the --subtitle option, `file[,language][,flags]`.
+/// Commas in the title are escaped as `\,`.
+#[derive(Debug, PartialEq)]
+pub struct SubtitleOption<'a> {
+    pub file_name: &'a str,
+    pub language: Option<Language>,
+    pub disposition: Disposition,
+    pub title: Option<Box<str>>,
+}
+
+fn rsplit_unescaped(s: &str) -> Option<(&str, &str)> {
+    let mut end = s.len();
+    while let Some(i) = s[..end].rfind(',') {
+        if !s[..i].ends_with('\\') {
+            return Some((&s[..i], &s[i + 1..]));
+        }
+        end = i;
+    }
+    None
+}
+
+impl<'a> SubtitleOption<'a> {
+    pub fn parse(subtitle_option: &'a str) -> Result<Self> {
+        // Trailing flags like default, forced or the title come after the language
+        let mut subtitle_option = subtitle_option;
+        let mut disposition = Disposition::default();
+        let mut title = None;
+        while let Some((rest, flag)) = rsplit_unescaped(subtitle_option) {
+            if let Some(flag_title) = flag.strip_prefix("title=") {
+                title = Some(flag_title.replace("\\,", ",").into());
+            } else {
+                let follows_language = rsplit_unescaped(rest)
+                    .is_some_and(|(_, language)| Language::new(language).is_ok());
+                match disposition.with_flag(flag) {
+                    Ok(flagged_disposition) => disposition = flagged_disposition,
+                    // Only flags come after the language
+                    Err(e) if follows_language => return Err(e),
+                    Err(_) => break,
+                }
+            }
+            subtitle_option = rest;
+        }
+
+        let (file_name, language) = match rsplit_unescaped(subtitle_option) {
+            None => (subtitle_option, None),
+            Some((file_name, language)) => {
+                let language = Language::new(language).map_err(|_| {
+                    anyhow!("Language or subtitle flag {} not supported.", language)
+                })?;
+                (file_name, Some(language))
+            }
+        };
+        Ok(SubtitleOption {
+            file_name,
+            language,
+            disposition,
+            title,
+        })
+    }
+}
 
 #[derive(Default)]
-pub struct SubtitleFileBuilder {
+pub struct SubtitleFileBuilder<'a> {
     file_name: Option<Box<str>>,
     language: Option<Language>,
+    disposition: Disposition,
+    title: Option<Box<str>>,
     format: Option<SubtitleFormat>,
     handling: Option<SubtitleHandling>,
-    is_original_subtitle_file: bool,
-    subtitle_logger: Option<SubtitleLogger>,
+    dry_run: bool,
+    language_encoders: Option<&'a LanguageEncoders>,
+    event_sink: Option<&'a dyn EventSink>,
+    index: usize,
 }
 
-impl SubtitleFileBuilder {
+impl<'a> SubtitleFileBuilder<'a> {
     pub fn new() -> Self {
         SubtitleFileBuilder {
             ..Default::default()
         }
     }
 
-    pub fn with_subtitle_logger(self, subtitle_logger: SubtitleLogger) -> Self {
+    /// Reports the conversion and parsing of the subtitle at the given position of the video.
+    pub fn with_event_sink(self, event_sink: &'a dyn EventSink, index: usize) -> Self {
         SubtitleFileBuilder {
-            subtitle_logger: Some(subtitle_logger),
+            event_sink: Some(event_sink),
+            index,
+            ..self
+        }
+    }
+
+    /// Avoids writing the fixed subtitle file.
+    pub fn with_dry_run(self, dry_run: bool) -> Self {
+        SubtitleFileBuilder { dry_run, ..self }
+    }
+
+    /// Replaces the encoders tried for some languages.
+    pub fn with_language_encoders(self, language_encoders: &'a LanguageEncoders) -> Self {
+        SubtitleFileBuilder {
+            language_encoders: Some(language_encoders),
             ..self
         }
     }
@@ -49,20 +130,12 @@ impl SubtitleFileBuilder {
                 file_name.as_ref()
             ));
         }
-        let format = SubtitleFormat::new(&file)?;
-        let encoders = self
-            .language
-            .as_ref()
-            .map_or(None, |v| v.preferred_encoders());
-
-        let (subtitle_file_name, is_transformed) =
-            get_file_with_utf8_encoding(file, &format, encoders, &subtitle_mode)?;
+        let format = SubtitleFormat::new(file)?;
 
         Ok(SubtitleFileBuilder {
-            file_name: Some(subtitle_file_name),
+            file_name: Some(file_name.as_ref().into()),
             format: Some(format),
             handling: Some(subtitle_mode),
-            is_original_subtitle_file: !is_transformed,
             ..self
         })
     }
@@ -74,17 +147,49 @@ impl SubtitleFileBuilder {
         }
     }
 
+    pub fn with_disposition(self, disposition: Disposition) -> Self {
+        SubtitleFileBuilder {
+            disposition,
+            ..self
+        }
+    }
+
+    pub fn with_title<S>(self, title: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        SubtitleFileBuilder {
+            title: Some(title.as_ref().into()),
+            ..self
+        }
+    }
+
     pub fn with_subtitle_option<S>(self, subtitle_option: S, mode: SubtitleHandling) -> Result<Self>
     where
         S: AsRef<str>,
     {
-        if !subtitle_option.as_ref().contains(",") {
-            self.with_file(subtitle_option, mode)
-        } else {
-            let (subtitle_file, language) = subtitle_option.as_ref().rsplit_once(',').unwrap();
-            self.with_language(Language::new(language)?)
-                .with_file(subtitle_file, mode)
+        let subtitle_option = SubtitleOption::parse(subtitle_option.as_ref())?;
+        let mut builder = self.with_disposition(subtitle_option.disposition);
+        if let Some(title) = subtitle_option.title {
+            builder = builder.with_title(title);
         }
+        if let Some(language) = subtitle_option.language {
+            builder = builder.with_language(language);
+        }
+        builder.with_file(subtitle_option.file_name, mode)
+    }
+
+    pub fn with_discovered_subtitle(
+        self,
+        subtitle: &DiscoveredSubtitle,
+        mode: SubtitleHandling,
+    ) -> Result<Self> {
+        let builder = self.with_disposition(subtitle.disposition);
+        let builder = match &subtitle.language {
+            Some(language) => builder.with_language(language.clone()),
+            None => builder,
+        };
+        builder.with_file(&subtitle.file_name, mode)
     }
 
     pub fn build(self) -> Result<SubtitleFile> {
@@ -97,33 +202,215 @@ impl SubtitleFileBuilder {
         let mode = self.handling.ok_or_else(|| {
             anyhow!("The subtitle mode is not defined. Please select a valid mode.")
         })?;
-        self.subtitle_logger
-            .map(|logger| logger.report_subtitle_parsing_done(&self.language, &file_name))
-            .unwrap_or(Ok(()))?;
+        let encoders = self.language.as_ref().and_then(|language| {
+            self.language_encoders
+                .and_then(|language_encoders| language_encoders.get(language).cloned())
+                .or_else(|| language.preferred_encoders())
+        });
 
-        return Ok(SubtitleFile {
-            file_name,
+        let (subtitle_file_name, encoding) = get_file_with_utf8_encoding(
+            Path::new(file_name.as_ref()),
+            &format,
+            encoders,
+            &mode,
+            self.dry_run,
+        )?;
+        if let Some(event_sink) = self.event_sink {
+            if let Some(encoding) = encoding {
+                event_sink.send(MuxEvent::EncodingConverted {
+                    index: self.index,
+                    file_name,
+                    encoding: encoding.name(),
+                    converted_file: subtitle_file_name.clone(),
+                })?;
+            }
+            event_sink.send(MuxEvent::SubtitleParsed {
+                index: self.index,
+                file_name: subtitle_file_name.clone(),
+                language: self.language.clone(),
+            })?;
+        }
+
+        Ok(SubtitleFile {
+            file_name: subtitle_file_name,
             format,
             language: self.language,
+            disposition: self.disposition,
+            title: self.title,
             mode,
-            is_original_subtitle_file: self.is_original_subtitle_file,
-        });
+            is_original_subtitle_file: encoding.is_none(),
+            is_written: !self.dry_run,
+        })
     }
 }
 
 pub struct SubtitleFile {
     pub language: Option<Language>,
+    pub disposition: Disposition,
+    pub title: Option<Box<str>>,
     pub format: SubtitleFormat,
     pub file_name: Box<str>,
     pub mode: SubtitleHandling,
     pub is_original_subtitle_file: bool,
+    pub is_written: bool,
+}
+
+impl SubtitleFile {
+    /// Counts the cues of the subtitle, to check none was lost in the output video.
+    pub fn count_cues(&self) -> Result<usize> {
+        let content = fs::read(self.file_name.as_ref())?;
+        let content = String::from_utf8_lossy(&content);
+        // Every cue has a single timing line
+        Ok(content
+            .lines()
+            .filter(|line| self.format.is_cue_timing(line))
+            .count())
+    }
 }
 
 impl Drop for SubtitleFile {
     fn drop(&mut self) {
-        if self.is_original_subtitle_file || !self.mode.should_remove_file() {
+        if self.is_original_subtitle_file || !self.is_written || !self.mode.should_remove_file() {
             return;
         }
-        std::fs::remove_file(self.file_name.as_ref()).unwrap();
+        // The file may have been removed already, which isn't worth failing the job
+        let _ = std::fs::remove_file(self.file_name.as_ref());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, sync::mpsc};
+
+    use tempfile::Builder;
+
+    use super::*;
+    use crate::event::ChannelSink;
+
+    #[test]
+    fn test_subtitle_option_with_flags() -> Result<()> {
+        let mut subtitle = Builder::new().suffix(".srt").tempfile()?;
+        subtitle.write_all(b"1\n00:00:01,000 --> 00:00:02,000\nHola\n")?;
+        let subtitle_option = format!(
+            "{},spanish,default,title=Spanish (Latin America),forced",
+            subtitle.path().display()
+        );
+        let subtitle_file = SubtitleFileBuilder::new()
+            .with_subtitle_option(subtitle_option, SubtitleHandling::Keep)?
+            .build()?;
+
+        assert_eq!(subtitle_file.language, Some(Language::Spanish));
+        assert_eq!(
+            subtitle_file.title.as_deref(),
+            Some("Spanish (Latin America)")
+        );
+        assert_eq!(
+            subtitle_file.disposition,
+            Disposition {
+                default: true,
+                forced: true,
+                hearing_impaired: false,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_subtitle_option_with_flags_and_no_language() -> Result<()> {
+        let mut subtitle = Builder::new().suffix(".srt").tempfile()?;
+        subtitle.write_all(b"1\n00:00:01,000 --> 00:00:02,000\nHello\n")?;
+        let subtitle_option = format!("{},forced", subtitle.path().display());
+        let subtitle_file = SubtitleFileBuilder::new()
+            .with_subtitle_option(subtitle_option, SubtitleHandling::Keep)?
+            .build()?;
+
+        assert_eq!(subtitle_file.language, None);
+        assert!(subtitle_file.disposition.forced);
+        assert_eq!(subtitle_file.count_cues()?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_sends_events() -> Result<()> {
+        let mut subtitle = Builder::new().suffix(".srt").tempfile()?;
+        subtitle.write_all(b"1\n00:00:01,000 --> 00:00:02,000\nCanci\xf3n\n")?;
+        let file_name: Box<str> = subtitle.path().to_str().unwrap().into();
+        let (sender, receiver) = mpsc::channel();
+        let event_sink = ChannelSink::new(sender);
+        let subtitle_file = SubtitleFileBuilder::new()
+            .with_event_sink(&event_sink, 1)
+            .with_dry_run(true)
+            .with_subtitle_option(format!("{},spanish", file_name), SubtitleHandling::Keep)?
+            .build()?;
+        drop(event_sink);
+
+        let events: Vec<MuxEvent> = receiver.iter().collect();
+        assert_eq!(
+            events,
+            [
+                MuxEvent::EncodingConverted {
+                    index: 1,
+                    file_name,
+                    encoding: "windows-1252",
+                    converted_file: subtitle_file.file_name.clone(),
+                },
+                MuxEvent::SubtitleParsed {
+                    index: 1,
+                    file_name: subtitle_file.file_name.clone(),
+                    language: Some(Language::Spanish),
+                }
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_settings_apply_in_any_order() -> Result<()> {
+        let mut subtitle = Builder::new().suffix(".srt").tempfile()?;
+        subtitle.write_all(b"1\n00:00:01,000 --> 00:00:02,000\nCanci\xf3n\n")?;
+        let (sender, receiver) = mpsc::channel();
+        let event_sink = ChannelSink::new(sender);
+        let subtitle_file = SubtitleFileBuilder::new()
+            .with_file(subtitle.path().to_str().unwrap(), SubtitleHandling::Keep)?
+            .with_language(Language::Spanish)
+            .with_dry_run(true)
+            .with_event_sink(&event_sink, 0)
+            .build()?;
+        drop(event_sink);
+
+        assert!(!subtitle_file.is_original_subtitle_file);
+        assert!(!Path::new(subtitle_file.file_name.as_ref()).exists());
+        assert_eq!(receiver.iter().count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_subtitle_option_with_escaped_title() -> Result<()> {
+        let subtitle_option = SubtitleOption::parse("sub.srt,english,title=Hello\\, World,sdh")?;
+        assert_eq!(subtitle_option.file_name, "sub.srt");
+        assert_eq!(subtitle_option.title.as_deref(), Some("Hello, World"));
+        assert!(subtitle_option.disposition.hearing_impaired);
+        Ok(())
+    }
+
+    #[test]
+    fn test_subtitle_option_with_unknown_flag() {
+        let e = SubtitleOption::parse("sub.srt,spanish,defualt").unwrap_err();
+        assert_eq!(e.to_string(), "Subtitle flag defualt not supported.");
+        let e = SubtitleOption::parse("sub.srt,defualt").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Language or subtitle flag defualt not supported."
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_subtitle_option_with_unknown_language() {
+        let subtitle = Builder::new().suffix(".srt").tempfile().unwrap();
+        let subtitle_option = format!("{},klingon,default", subtitle.path().display());
+        SubtitleFileBuilder::new()
+            .with_subtitle_option(subtitle_option, SubtitleHandling::Keep)
+            .unwrap();
     }
 }
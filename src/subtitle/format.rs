@@ -6,6 +6,8 @@ use crate::utils::get_file_extension;
 
 pub enum SubtitleFormat {
     Srt,
+    Ass,
+    Ssa,
 }
 
 impl SubtitleFormat {
@@ -13,6 +15,8 @@ impl SubtitleFormat {
         let file_extension = get_file_extension(file)?;
         match file_extension.as_ref() {
             "srt" => Ok(SubtitleFormat::Srt),
+            "ass" => Ok(SubtitleFormat::Ass),
+            "ssa" => Ok(SubtitleFormat::Ssa),
             extension => Err(anyhow!("File extension {} not supported.", extension)),
         }
     }
@@ -20,8 +24,54 @@ impl SubtitleFormat {
     pub fn to_extension(&self) -> Box<str> {
         match self {
             SubtitleFormat::Srt => "srt".into(),
+            SubtitleFormat::Ass => "ass".into(),
+            SubtitleFormat::Ssa => "ssa".into(),
         }
     }
+
+    /// The name of the ffmpeg demuxer reading the format, which handles SSA as ASS.
+    pub fn to_ffmpeg_format(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Ass | SubtitleFormat::Ssa => "ass",
+        }
+    }
+
+    /// Whether the line starts a cue, like `00:00:01,000 --> 00:00:02,500` in SRT or a
+    /// `Dialogue:` line in ASS and SSA.
+    pub fn is_cue_timing(&self, line: &str) -> bool {
+        match self {
+            SubtitleFormat::Srt => {
+                let Some((start, end)) = line.trim().split_once("-->") else {
+                    return false;
+                };
+                // Some files place the cue with coordinates after the end time
+                let end = end.split_whitespace().next().unwrap_or_default();
+                is_srt_timestamp(start.trim()) && is_srt_timestamp(end)
+            }
+            SubtitleFormat::Ass | SubtitleFormat::Ssa => line.trim_start().starts_with("Dialogue:"),
+        }
+    }
+}
+
+fn is_srt_timestamp(timestamp: &str) -> bool {
+    let Some((time, milliseconds)) = timestamp.split_once([',', '.']) else {
+        return false;
+    };
+    let is_number = |digits: &str, len: usize| {
+        digits.len() == len && digits.bytes().all(|b| b.is_ascii_digit())
+    };
+    let parts: Vec<&str> = time.split(':').collect();
+    match parts.as_slice() {
+        [hours, minutes, seconds] => {
+            !hours.is_empty()
+                && hours.bytes().all(|b| b.is_ascii_digit())
+                && is_number(minutes, 2)
+                && is_number(seconds, 2)
+                && is_number(milliseconds, 3)
+        }
+        _ => false,
+    }
 }
 
 #[cfg(test)]
@@ -32,4 +82,23 @@ mod tests {
     fn test_srt_file() {
         assert_eq!(SubtitleFormat::Srt.to_extension().as_ref(), "srt")
     }
+
+    #[test]
+    fn test_srt_cue_timing() {
+        let format = SubtitleFormat::Srt;
+        assert!(format.is_cue_timing("00:00:01,000 --> 00:00:02,500"));
+        assert!(format.is_cue_timing("00:00:01.000-->00:00:02.500 X1:40 X2:600\r"));
+        assert!(!format.is_cue_timing("He said --> go left"));
+        assert!(!format.is_cue_timing("00:00:01 --> 00:00:02"));
+    }
+
+    #[test]
+    fn test_ass_file() -> Result<()> {
+        let format = SubtitleFormat::new(Path::new("subtitle.ssa"))?;
+        assert_eq!(format.to_extension().as_ref(), "ssa");
+        assert_eq!(format.to_ffmpeg_format(), "ass");
+        assert!(format.is_cue_timing("Dialogue: 0,0:00:01.00,0:00:02.50,Default,,0,0,0,,Hello"));
+        assert!(!format.is_cue_timing("Comment: 0,0:00:01.00,0:00:02.50,Default,,0,0,0,,Hello"));
+        Ok(())
+    }
 }
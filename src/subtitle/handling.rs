@@ -5,7 +5,7 @@ use clap::ValueEnum;
 
 use crate::utils::get_file_stem;
 
-#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum SubtitleHandling {
     /// Keep all generated subtitle files when fixing the encoding.
     Keep,
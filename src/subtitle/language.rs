@@ -1,8 +1,11 @@
-use std::fmt;
+use std::{collections::HashMap, fmt};
 
 use anyhow::{anyhow, Result};
 use encoding_rs::Encoding;
+use serde::Serialize;
 
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Language {
     English,
     French,
@@ -22,6 +25,19 @@ impl Language {
         }
     }
 
+    /// Builds a language from an ISO 639 code as found in container metadata.
+    pub fn from_metadata_tag<S>(tag: S) -> Option<Self>
+    where
+        S: AsRef<str>,
+    {
+        match tag.as_ref().to_lowercase().as_str() {
+            "spa" | "es" => Some(Self::Spanish),
+            "eng" | "en" => Some(Self::English),
+            "fre" | "fra" | "fr" => Some(Self::French),
+            _ => None,
+        }
+    }
+
     pub fn to_metadata_tag(&self) -> Box<str> {
         match self {
             Language::Spanish => "spa".into(),
@@ -30,14 +46,38 @@ impl Language {
         }
     }
 
-    pub fn preferred_encoders(&self) -> Option<Box<[&'static Encoding]>> {
+    /// The ISO 639-1 code of the language, as used in file names.
+    pub fn to_short_tag(&self) -> &'static str {
         match self {
-            _ => Some(Box::new([
-                encoding_rs::WINDOWS_1252,
-                encoding_rs::ISO_8859_15,
-            ])),
+            Language::Spanish => "es",
+            Language::English => "en",
+            Language::French => "fr",
         }
     }
+
+    pub fn preferred_encoders(&self) -> Option<Box<[&'static Encoding]>> {
+        Some(Box::new([
+            encoding_rs::WINDOWS_1252,
+            encoding_rs::ISO_8859_15,
+        ]))
+    }
+}
+
+/// The encoders tried for the subtitles of each language, replacing the preferred ones.
+pub type LanguageEncoders = HashMap<Language, Box<[&'static Encoding]>>;
+
+pub fn is_same_language<A, B>(tag: A, other_tag: B) -> bool
+where
+    A: AsRef<str>,
+    B: AsRef<str>,
+{
+    match (
+        Language::from_metadata_tag(&tag),
+        Language::from_metadata_tag(&other_tag),
+    ) {
+        (Some(language), Some(other_language)) => language == other_language,
+        _ => tag.as_ref().eq_ignore_ascii_case(other_tag.as_ref()),
+    }
 }
 
 impl fmt::Display for Language {
@@ -68,4 +108,18 @@ mod tests {
     fn test_french_tag() {
         assert_eq!(Language::French.to_metadata_tag().as_ref(), "fre")
     }
+
+    #[test]
+    fn test_from_metadata_tag() {
+        assert_eq!(Language::from_metadata_tag("fra"), Some(Language::French));
+        assert_eq!(Language::from_metadata_tag("EN"), Some(Language::English));
+        assert_eq!(Language::from_metadata_tag("ger"), None);
+    }
+
+    #[test]
+    fn test_is_same_language() {
+        assert!(is_same_language("fre", "fra"));
+        assert!(is_same_language("ger", "GER"));
+        assert!(!is_same_language("spa", "eng"));
+    }
 }
@@ -0,0 +1,340 @@
+use std::path::Path;
+
+use super::discovery::{split_subtitle_tags, DiscoveredSubtitle};
+use crate::utils::get_file_stem;
+
+const MIN_NAME_SIMILARITY: f64 = 0.6;
+
+/// Season and episode numbers found in a file name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EpisodeId {
+    pub season: Option<u32>,
+    pub episode: u32,
+}
+
+impl EpisodeId {
+    fn matches(&self, other: &EpisodeId) -> bool {
+        let is_same_season = match (self.season, other.season) {
+            (Some(season), Some(other_season)) => season == other_season,
+            _ => true,
+        };
+        is_same_season && self.episode == other.episode
+    }
+}
+
+fn get_tokens(name: &str) -> Vec<String> {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+        .collect()
+}
+
+fn parse_number(digits: &str, max_digits: usize) -> Option<u32> {
+    if digits.is_empty() || digits.len() > max_digits || !digits.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+fn parse_episode_token(token: &str) -> Option<EpisodeId> {
+    if let Some((season, episode)) = token.strip_prefix('s').and_then(|t| t.split_once('e')) {
+        return Some(EpisodeId {
+            season: Some(parse_number(season, 2)?),
+            episode: parse_number(episode, 3)?,
+        });
+    }
+    if let Some((season, episode)) = token.split_once('x') {
+        // Always two digits for the episode so `1x2` isn't read as an episode
+        if episode.len() < 2 {
+            return None;
+        }
+        return Some(EpisodeId {
+            season: Some(parse_number(season, 2)?),
+            episode: parse_number(episode, 3)?,
+        });
+    }
+    let episode = token
+        .strip_prefix("ep")
+        .or_else(|| token.strip_prefix('e'))?;
+    Some(EpisodeId {
+        season: None,
+        episode: parse_number(episode, 3)?,
+    })
+}
+
+fn find_episode_id(tokens: &[String]) -> Option<(usize, EpisodeId)> {
+    tokens.iter().enumerate().find_map(|(i, token)| {
+        if token == "episode" || token == "ep" {
+            let episode = parse_number(tokens.get(i + 1)?, 3)?;
+            return Some((
+                i,
+                EpisodeId {
+                    season: None,
+                    episode,
+                },
+            ));
+        }
+        Some((i, parse_episode_token(token)?))
+    })
+}
+
+/// Finds identifiers like `S01E02`, `1x02`, `Episode 2` or `Ep 02` in a file name.
+pub fn parse_episode_id(name: &str) -> Option<EpisodeId> {
+    find_episode_id(&get_tokens(name)).map(|(_, episode_id)| episode_id)
+}
+
+fn get_show_name(name: &str) -> String {
+    let name = match name.strip_prefix('[').and_then(|rest| rest.split_once(']')) {
+        Some((_, rest)) => rest,
+        None => name,
+    };
+    let tokens = get_tokens(name);
+    let end = find_episode_id(&tokens).map_or(tokens.len(), |(i, _)| i);
+    tokens[..end].join(" ")
+}
+
+/// Gets the release group from names like `[Group] Show - 02` or `Show.S01E02.WEB-Group`.
+pub fn get_release_group(name: &str) -> Option<Box<str>> {
+    if let Some(rest) = name.strip_prefix('[') {
+        let (group, _) = rest.split_once(']')?;
+        return Some(group.trim().to_lowercase().into());
+    }
+    let (_, group) = name.rsplit_once('-')?;
+    let is_group = !group.is_empty() && group.chars().all(|c| c.is_alphanumeric());
+    is_group.then(|| group.to_lowercase().into())
+}
+
+fn get_number_tokens(name: &str) -> Vec<String> {
+    get_tokens(name)
+        .into_iter()
+        .filter(|token| token.bytes().all(|b| b.is_ascii_digit()))
+        .collect()
+}
+
+fn get_name_similarity(name: &str, other_name: &str) -> f64 {
+    let normalize = |name: &str| {
+        get_tokens(name)
+            .into_iter()
+            .filter(|token| parse_episode_token(token).is_none())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    strsim::sorensen_dice(&normalize(name), &normalize(other_name))
+}
+
+fn get_match_score(video_name: &str, subtitle_name: &str) -> Option<f64> {
+    let similarity = get_name_similarity(video_name, subtitle_name);
+    let video_numbers = get_number_tokens(video_name);
+    let subtitle_numbers = get_number_tokens(subtitle_name);
+    let mut score = match (
+        parse_episode_id(video_name),
+        parse_episode_id(subtitle_name),
+    ) {
+        // Release details after the episode identifier often differ, unlike the show
+        (Some(video_id), Some(subtitle_id)) if video_id.matches(&subtitle_id) => {
+            let show_similarity =
+                strsim::sorensen_dice(&get_show_name(video_name), &get_show_name(subtitle_name));
+            if show_similarity < MIN_NAME_SIMILARITY {
+                return None;
+            }
+            1.0
+        }
+        (Some(_), Some(_)) => return None,
+        // Bare numbers are usually episodes, so different ones can't be paired
+        _ if !video_numbers.is_empty()
+            && !subtitle_numbers.is_empty()
+            && video_numbers != subtitle_numbers =>
+        {
+            return None
+        }
+        _ if similarity >= MIN_NAME_SIMILARITY => 0.0,
+        _ => return None,
+    };
+    score += similarity;
+    if let (Some(video_group), Some(subtitle_group)) = (
+        get_release_group(video_name),
+        get_release_group(subtitle_name),
+    ) {
+        if video_group == subtitle_group {
+            score += 0.5;
+        }
+    }
+    Some(score)
+}
+
+/// Pairs each subtitle file with the video it most likely belongs to, keeping the best one
+/// for each language and disposition of a video. Returns the subtitles of every video in
+/// the same order as the videos.
+pub fn match_subtitles<V, S>(
+    video_files: &[V],
+    subtitle_files: &[S],
+) -> Vec<Vec<DiscoveredSubtitle>>
+where
+    V: AsRef<str>,
+    S: AsRef<str>,
+{
+    let video_names: Vec<Option<Box<str>>> = video_files
+        .iter()
+        .map(|video_file| get_file_stem(Path::new(video_file.as_ref())).ok())
+        .collect();
+
+    let mut matches: Vec<Vec<(f64, DiscoveredSubtitle)>> = vec![Vec::new(); video_files.len()];
+    for subtitle_file in subtitle_files.iter() {
+        let Ok(subtitle_stem) = get_file_stem(Path::new(subtitle_file.as_ref())) else {
+            continue;
+        };
+        let (subtitle_name, language, disposition) = split_subtitle_tags(&subtitle_stem);
+        let best_match = video_names
+            .iter()
+            .enumerate()
+            .filter_map(|(i, video_name)| {
+                Some((i, get_match_score(video_name.as_deref()?, subtitle_name)?))
+            })
+            .max_by(|(_, score), (_, other_score)| score.total_cmp(other_score));
+        let Some((i, score)) = best_match else {
+            continue;
+        };
+        let subtitle = DiscoveredSubtitle {
+            file_name: subtitle_file.as_ref().into(),
+            language,
+            disposition,
+        };
+        let same_track = matches[i]
+            .iter_mut()
+            .find(|(_, other_subtitle)| other_subtitle.is_same_track(&subtitle));
+        match same_track {
+            Some(same_track) if same_track.0 < score => *same_track = (score, subtitle),
+            Some(_) => {}
+            None => matches[i].push((score, subtitle)),
+        }
+    }
+    matches
+        .into_iter()
+        .map(|subtitles| {
+            subtitles
+                .into_iter()
+                .map(|(_, subtitle)| subtitle)
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::subtitle::language::Language;
+
+    #[test]
+    fn test_parse_episode_id() {
+        let episode_id = |season, episode| Some(EpisodeId { season, episode });
+        assert_eq!(
+            parse_episode_id("Show.S01E02.1080p"),
+            episode_id(Some(1), 2)
+        );
+        assert_eq!(parse_episode_id("show 1x02 720p"), episode_id(Some(1), 2));
+        assert_eq!(parse_episode_id("Show - Episode 12"), episode_id(None, 12));
+        assert_eq!(parse_episode_id("[Group] Show - Ep03"), episode_id(None, 3));
+        assert_eq!(parse_episode_id("Show 1920x1080"), None);
+        assert_eq!(parse_episode_id("Show"), None);
+    }
+
+    #[test]
+    fn test_get_release_group() {
+        assert_eq!(
+            get_release_group("[SubGroup] Show - 02").as_deref(),
+            Some("subgroup")
+        );
+        assert_eq!(
+            get_release_group("Show.S01E02.WEB-NTb").as_deref(),
+            Some("ntb")
+        );
+        assert_eq!(get_release_group("Show S01E02"), None);
+    }
+
+    #[test]
+    fn test_match_subtitles_by_episode() {
+        let video_files = [
+            "/tv/Show/Show.S01E01.1080p.WEB-NTb.mkv",
+            "/tv/Show/Show.S01E02.1080p.WEB-NTb.mkv",
+        ];
+        let subtitle_files = [
+            "/tv/Show/subs/show 1x02.en.srt",
+            "/tv/Show/subs/Show.S01E01.WEB-NTb.spa.forced.srt",
+            "/tv/Show/subs/Show.S02E01.srt",
+            "/tv/Show/subs/Another Thing.srt",
+        ];
+        let matches = match_subtitles(&video_files, &subtitle_files);
+
+        let get_subtitles = |i: usize| -> Vec<(&str, Option<Language>, bool)> {
+            matches[i]
+                .iter()
+                .map(|subtitle| {
+                    (
+                        subtitle.file_name.as_ref(),
+                        subtitle.language.clone(),
+                        subtitle.disposition.forced,
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(
+            get_subtitles(0),
+            [(subtitle_files[1], Some(Language::Spanish), true)]
+        );
+        assert_eq!(
+            get_subtitles(1),
+            [(subtitle_files[0], Some(Language::English), false)]
+        );
+    }
+
+    #[test]
+    fn test_match_subtitles_of_another_show_or_season() {
+        let video_files = ["/tv/Show.S01E02.mkv", "/tv/Show.S01E03.mkv"];
+        let subtitle_files = [
+            "/tv/Other Show.S01E02.en.srt",
+            "/tv/Show Episode 2.en.srt",
+            "/tv/Show.S02E03.en.srt",
+            "/tv/Show Ep 03.fr.srt",
+        ];
+        let matches = match_subtitles(&video_files, &subtitle_files);
+        assert_eq!(matches[0].len(), 1);
+        assert_eq!(matches[0][0].file_name.as_ref(), subtitle_files[1]);
+        assert_eq!(matches[1].len(), 1);
+        assert_eq!(matches[1][0].file_name.as_ref(), subtitle_files[3]);
+    }
+
+    #[test]
+    fn test_match_subtitles_by_bare_episode_number() {
+        let video_files = ["/anime/Show - 02.mkv"];
+        let subtitle_files = ["/anime/Show - 03.en.srt", "/anime/Show - 02.fr.srt"];
+        let matches = match_subtitles(&video_files, &subtitle_files);
+        assert_eq!(matches[0].len(), 1);
+        assert_eq!(matches[0][0].file_name.as_ref(), subtitle_files[1]);
+    }
+
+    #[test]
+    fn test_match_one_subtitle_per_language() {
+        let video_files = ["/tv/Show.S01E01.WEB-NTb.mkv"];
+        let subtitle_files = [
+            "/tv/subs/Show 1x01.en.srt",
+            "/tv/subs/Show.S01E01.WEB-NTb.en.srt",
+            "/tv/subs/Show.S01E01.en.forced.srt",
+        ];
+        let matches = match_subtitles(&video_files, &subtitle_files);
+        let file_names: Vec<&str> = matches[0]
+            .iter()
+            .map(|subtitle| subtitle.file_name.as_ref())
+            .collect();
+        assert_eq!(file_names, [subtitle_files[1], subtitle_files[2]]);
+    }
+
+    #[test]
+    fn test_match_subtitles_by_name_similarity() {
+        let video_files = ["/movies/The Great Movie (2020).mkv", "/movies/Other.mkv"];
+        let subtitle_files = ["/movies/the.great.movie.2020.fr.srt"];
+        let matches = match_subtitles(&video_files, &subtitle_files);
+        assert_eq!(matches[0].len(), 1);
+        assert!(matches[1].is_empty());
+    }
+}
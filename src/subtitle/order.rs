@@ -0,0 +1,271 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Error, Result};
+
+use super::{file::SubtitleFile, language::Language};
+use crate::video::stream::StreamInfo;
+
+/// A subtitle track of the output video.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SubtitleTrack<'a> {
+    /// The new subtitle at the given position of the provided subtitles.
+    New(usize),
+
+    /// A pre-existing subtitle stream of the input video.
+    Existing(&'a StreamInfo),
+}
+
+/// References a track when providing an explicit order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrackReference {
+    /// The position of the new subtitle, starting from 1 as given in the command line.
+    New(usize),
+
+    /// The absolute index of the pre-existing stream, as shown by `subtrack info`.
+    Existing(usize),
+}
+
+impl FromStr for TrackReference {
+    type Err = Error;
+
+    fn from_str(reference: &str) -> Result<Self> {
+        let invalid_reference = || {
+            anyhow!(
+                "Track {} must be a stream index or new:<N> for the N-th new subtitle.",
+                reference
+            )
+        };
+        match reference.strip_prefix("new:") {
+            Some(position) => match position.parse() {
+                Ok(position) if position > 0 => Ok(TrackReference::New(position)),
+                _ => Err(invalid_reference()),
+            },
+            None => reference
+                .parse()
+                .map(TrackReference::Existing)
+                .map_err(|_| invalid_reference()),
+        }
+    }
+}
+
+impl fmt::Display for TrackReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrackReference::New(position) => write!(f, "new:{}", position),
+            TrackReference::Existing(index) => write!(f, "{}", index),
+        }
+    }
+}
+
+impl TrackReference {
+    fn matches(&self, track: &SubtitleTrack) -> bool {
+        match (self, track) {
+            (TrackReference::New(position), SubtitleTrack::New(i)) => *position == i + 1,
+            (TrackReference::Existing(index), SubtitleTrack::Existing(stream)) => {
+                *index == stream.index
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubtitleOrder {
+    /// Place the new subtitles before the pre-existing ones.
+    NewFirst,
+
+    /// Place the pre-existing subtitles before the new ones.
+    ExistingFirst,
+
+    /// Sort all subtitles by language, leaving the ones without language at the end.
+    Language,
+
+    /// Place the listed tracks first and the rest after them with the new ones first.
+    Explicit(Vec<TrackReference>),
+}
+
+impl FromStr for SubtitleOrder {
+    type Err = Error;
+
+    fn from_str(order: &str) -> Result<Self> {
+        match order {
+            "new-first" => Ok(SubtitleOrder::NewFirst),
+            "existing-first" => Ok(SubtitleOrder::ExistingFirst),
+            "language" => Ok(SubtitleOrder::Language),
+            order => Ok(SubtitleOrder::Explicit(
+                order
+                    .split(',')
+                    .map(|reference| reference.trim().parse())
+                    .collect::<Result<_>>()?,
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SubtitleOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubtitleOrder::NewFirst => write!(f, "new-first"),
+            SubtitleOrder::ExistingFirst => write!(f, "existing-first"),
+            SubtitleOrder::Language => write!(f, "language"),
+            SubtitleOrder::Explicit(references) => write!(
+                f,
+                "{}",
+                references
+                    .iter()
+                    .map(|reference| reference.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+        }
+    }
+}
+
+fn get_language_key(track: &SubtitleTrack, subtitles: &[SubtitleFile]) -> Option<Box<str>> {
+    match track {
+        SubtitleTrack::New(i) => subtitles[*i]
+            .language
+            .as_ref()
+            .map(|language| language.to_metadata_tag()),
+        SubtitleTrack::Existing(stream) => stream.language.as_ref().map(|tag| {
+            Language::from_metadata_tag(tag)
+                .map_or_else(|| tag.to_lowercase().into(), |l| l.to_metadata_tag())
+        }),
+    }
+}
+
+impl SubtitleOrder {
+    /// Arranges the new subtitles and the kept pre-existing streams into the output tracks.
+    pub fn arrange<'a>(
+        &self,
+        subtitles: &[SubtitleFile],
+        kept_streams: &[&'a StreamInfo],
+    ) -> Result<Vec<SubtitleTrack<'a>>> {
+        let new_tracks = (0..subtitles.len()).map(SubtitleTrack::New);
+        let existing_tracks = kept_streams
+            .iter()
+            .map(|stream| SubtitleTrack::Existing(stream));
+        let mut tracks: Vec<SubtitleTrack> = new_tracks.chain(existing_tracks).collect();
+
+        match self {
+            SubtitleOrder::NewFirst => {}
+            SubtitleOrder::ExistingFirst => tracks.rotate_left(subtitles.len()),
+            SubtitleOrder::Language => tracks.sort_by_key(|track| {
+                let key = get_language_key(track, subtitles);
+                (key.is_none(), key)
+            }),
+            SubtitleOrder::Explicit(references) => {
+                let mut ordered_tracks = Vec::with_capacity(tracks.len());
+                for reference in references.iter() {
+                    let position = tracks
+                        .iter()
+                        .position(|track| reference.matches(track))
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "Track {} in the subtitle order is not part of the output or is repeated.",
+                                reference
+                            )
+                        })?;
+                    ordered_tracks.push(tracks.remove(position));
+                }
+                ordered_tracks.extend(tracks);
+                tracks = ordered_tracks;
+            }
+        }
+        Ok(tracks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        subtitle::{disposition::Disposition, format::SubtitleFormat, handling::SubtitleHandling},
+        video::stream::StreamKind,
+    };
+
+    fn get_subtitles() -> Vec<SubtitleFile> {
+        let subtitle = |language| SubtitleFile {
+            language,
+            disposition: Disposition::default(),
+            title: None,
+            format: SubtitleFormat::Srt,
+            file_name: "subtitle.srt".into(),
+            mode: SubtitleHandling::Keep,
+            is_original_subtitle_file: true,
+            is_written: true,
+        };
+        vec![subtitle(Some(Language::Spanish)), subtitle(None)]
+    }
+
+    fn get_streams() -> Vec<StreamInfo> {
+        let stream = |index, language: &str| StreamInfo {
+            index,
+            kind: StreamKind::Subtitle,
+            language: Some(language.into()),
+            ..Default::default()
+        };
+        vec![stream(2, "fra"), stream(3, "eng")]
+    }
+
+    fn get_track_names(tracks: &[SubtitleTrack]) -> Vec<String> {
+        tracks
+            .iter()
+            .map(|track| match track {
+                SubtitleTrack::New(i) => format!("new:{}", i + 1),
+                SubtitleTrack::Existing(stream) => stream.index.to_string(),
+            })
+            .collect()
+    }
+
+    fn arrange(order: &str) -> Result<Vec<String>> {
+        let streams = get_streams();
+        let kept_streams: Vec<&StreamInfo> = streams.iter().collect();
+        let tracks = order
+            .parse::<SubtitleOrder>()?
+            .arrange(&get_subtitles(), &kept_streams)?;
+        Ok(get_track_names(&tracks))
+    }
+
+    #[test]
+    fn test_new_first() -> Result<()> {
+        assert_eq!(arrange("new-first")?, ["new:1", "new:2", "2", "3"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_existing_first() -> Result<()> {
+        assert_eq!(arrange("existing-first")?, ["2", "3", "new:1", "new:2"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sorted_by_language() -> Result<()> {
+        assert_eq!(arrange("language")?, ["3", "2", "new:1", "new:2"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_explicit_order() -> Result<()> {
+        assert_eq!(arrange("3, new:2")?, ["3", "new:2", "new:1", "2"]);
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_explicit_order_with_unknown_track() {
+        arrange("new:3").unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_explicit_order_with_repeated_track() {
+        arrange("2,2").unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_invalid_order() {
+        arrange("new:0").unwrap();
+    }
+}
@@ -0,0 +1,157 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Error, Result};
+
+use super::language::{is_same_language, Language};
+use crate::video::stream::{MediaInfo, StreamInfo, StreamKind};
+
+/// Selects pre-existing subtitle streams of a video.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubtitleSelector {
+    /// The absolute index of the stream, as shown by `subtrack info`.
+    Index(usize),
+
+    /// The language tag of the stream.
+    Language(Box<str>),
+}
+
+impl FromStr for SubtitleSelector {
+    type Err = Error;
+
+    fn from_str(selector: &str) -> Result<Self> {
+        if let Ok(index) = selector.parse() {
+            return Ok(SubtitleSelector::Index(index));
+        }
+        if let Ok(language) = Language::new(selector) {
+            return Ok(SubtitleSelector::Language(language.to_metadata_tag()));
+        }
+        if selector.len() == 3 && selector.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Ok(SubtitleSelector::Language(selector.to_lowercase().into()));
+        }
+        Err(anyhow!(
+            "Subtitle selector {} must be a stream index, a language or a three letter language tag.",
+            selector
+        ))
+    }
+}
+
+impl fmt::Display for SubtitleSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubtitleSelector::Index(index) => write!(f, "{}", index),
+            SubtitleSelector::Language(tag) => write!(f, "{}", tag),
+        }
+    }
+}
+
+impl SubtitleSelector {
+    pub fn matches(&self, stream: &StreamInfo) -> bool {
+        match self {
+            SubtitleSelector::Index(index) => stream.index == *index,
+            SubtitleSelector::Language(tag) => stream
+                .language
+                .as_ref()
+                .is_some_and(|language| is_same_language(language, tag)),
+        }
+    }
+}
+
+/// Finds the subtitle streams of the video matched by any of the selectors.
+pub fn select_subtitle_streams<'a>(
+    media_info: &'a MediaInfo,
+    selectors: &[SubtitleSelector],
+) -> Result<Vec<&'a StreamInfo>> {
+    for selector in selectors.iter() {
+        if let SubtitleSelector::Index(index) = selector {
+            let stream = media_info
+                .get_stream(*index)
+                .ok_or_else(|| anyhow!("The video doesn't have a stream with index {}.", index))?;
+            if stream.kind != StreamKind::Subtitle {
+                return Err(anyhow!(
+                    "Stream {} is a {} stream, not a subtitle stream.",
+                    index,
+                    stream.kind
+                ));
+            }
+        }
+        if !media_info.subtitle_streams().any(|s| selector.matches(s)) {
+            return Err(anyhow!("No subtitle stream matches {}.", selector));
+        }
+    }
+
+    Ok(media_info
+        .subtitle_streams()
+        .filter(|stream| selectors.iter().any(|selector| selector.matches(stream)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_media_info() -> MediaInfo {
+        let stream = |index, kind, language: Option<&str>| StreamInfo {
+            index,
+            kind,
+            language: language.map(|l| l.into()),
+            ..Default::default()
+        };
+        MediaInfo {
+            streams: vec![
+                stream(0, StreamKind::Video, None),
+                stream(1, StreamKind::Subtitle, Some("eng")),
+                stream(2, StreamKind::Subtitle, Some("spa")),
+                stream(3, StreamKind::Subtitle, Some("fra")),
+            ],
+            duration: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_selectors() -> Result<()> {
+        assert_eq!("2".parse::<SubtitleSelector>()?, SubtitleSelector::Index(2));
+        assert_eq!(
+            "Spanish".parse::<SubtitleSelector>()?,
+            SubtitleSelector::Language("spa".into())
+        );
+        assert_eq!(
+            "GER".parse::<SubtitleSelector>()?,
+            SubtitleSelector::Language("ger".into())
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_parse_invalid_selector() {
+        "not a language".parse::<SubtitleSelector>().unwrap();
+    }
+
+    #[test]
+    fn test_select_by_language_and_index() -> Result<()> {
+        let media_info = get_media_info();
+        let selectors = [
+            SubtitleSelector::Language("fre".into()),
+            SubtitleSelector::Index(1),
+        ];
+        let indices: Vec<usize> = select_subtitle_streams(&media_info, &selectors)?
+            .iter()
+            .map(|s| s.index)
+            .collect();
+        assert_eq!(indices, vec![1, 3]);
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_select_non_subtitle_stream() {
+        select_subtitle_streams(&get_media_info(), &[SubtitleSelector::Index(0)]).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_select_missing_language() {
+        let selectors = [SubtitleSelector::Language("ger".into())];
+        select_subtitle_streams(&get_media_info(), &selectors).unwrap();
+    }
+}
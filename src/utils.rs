@@ -1,42 +1,67 @@
-use std::path::Path;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::{anyhow, Result};
 
 use crate::{
-    logger::CLILogger,
-    video::file::{VideoFile, VideoFileBuilder},
+    event::{EventSink, MuxEvent},
+    video::{
+        collision::CollisionPolicy,
+        file::{VideoFile, VideoFileBuilder},
+    },
 };
 
+/// Gets the output video, which is None when it already exists and must be skipped.
 pub fn parse_output_file<O, I>(
-    output_file: Option<O>,
+    output_file_name: O,
     input_file: I,
-    logger: &CLILogger,
-) -> Result<VideoFile>
+    policy: CollisionPolicy,
+    event_sink: &dyn EventSink,
+) -> Result<Option<VideoFile>>
 where
     O: AsRef<str>,
     I: AsRef<str>,
 {
     let input_file_path = Path::new(input_file.as_ref());
-    if output_file.is_none() {
-        let stem = get_file_stem(input_file_path)?;
-        let extension = get_file_extension(input_file_path)?;
-        return parse_output_file(
-            format!("{}-subs.{}", stem, extension).into(),
-            input_file,
-            logger,
-        );
-    }
-
-    let output_file_name = output_file.unwrap();
-    logger.report_output_file_parsing(&output_file_name)?;
+    event_sink.send(MuxEvent::OutputResolved {
+        file_name: output_file_name.as_ref().into(),
+    })?;
     let output_file_path = Path::new(output_file_name.as_ref());
-    if output_file_path == input_file_path {
+    if is_same_file(output_file_path, input_file_path) {
         return Err(anyhow!("Output file can't be the same path as input file"));
     }
 
+    let Some(output_file_name) = policy.resolve(output_file_name)? else {
+        return Ok(None);
+    };
     VideoFileBuilder::new()
-        .with_output_file(output_file_name)?
+        .with_output_file(output_file_name, policy)?
         .build()
+        .map(Some)
+}
+
+/// Resolves the file to an absolute path without `.`, `..` or symbolic links, as far as
+/// its directory exists.
+pub fn canonicalize_file(file: &Path) -> Option<PathBuf> {
+    if let Ok(canonical_file) = fs::canonicalize(file) {
+        return Some(canonical_file);
+    }
+    let directory = match file.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+    Some(fs::canonicalize(directory).ok()?.join(file.file_name()?))
+}
+
+/// Whether both names point to the same file, however they are spelled.
+pub fn is_same_file(file: &Path, other_file: &Path) -> bool {
+    match (canonicalize_file(file), canonicalize_file(other_file)) {
+        (Some(file), Some(other_file)) => file == other_file,
+        _ => file == other_file,
+    }
 }
 
 pub fn get_file_stem(file: &Path) -> Result<Box<str>> {
@@ -71,3 +96,106 @@ pub fn get_file_extension(file: &Path) -> Result<Box<str>> {
         })
         .map(|s| s.into())
 }
+
+pub fn format_duration(seconds: f64) -> String {
+    let milliseconds = (seconds * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        milliseconds / 3_600_000,
+        milliseconds / 60_000 % 60,
+        milliseconds / 1000 % 60,
+        milliseconds % 1000
+    )
+}
+
+/// Formats the UTC date of the time as YYYY-MM-DD.
+pub fn format_date(time: SystemTime) -> String {
+    let days = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() / 86_400) as i64;
+
+    // Convert the days since 1970-01-01 to a civil date, with years starting in March
+    let shifted_days = days + 719_468;
+    let era = shifted_days.div_euclid(146_097);
+    let day_of_era = shifted_days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = era * 400 + year_of_era + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Quotes an argument so it can be pasted in a POSIX shell.
+pub fn shell_quote(arg: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-./:=,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        return arg.to_owned();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::event::SilentSink;
+
+    #[test]
+    fn test_output_is_the_input_under_another_name() -> Result<()> {
+        let directory = TempDir::new()?;
+        let input_file = directory.path().join("video.mkv");
+        fs::write(&input_file, b"original")?;
+        fs::create_dir(directory.path().join("subs"))?;
+        let input_file_name = input_file.to_str().unwrap();
+
+        let spellings = [
+            directory.path().join(".").join("video.mkv"),
+            directory.path().join("subs").join("..").join("video.mkv"),
+        ];
+        for output_file in spellings.iter() {
+            let result = parse_output_file(
+                output_file.to_str().unwrap(),
+                input_file_name,
+                CollisionPolicy::Overwrite,
+                &SilentSink,
+            );
+            assert!(result.is_err());
+        }
+        assert_eq!(fs::read(&input_file)?, b"original");
+
+        let output_file = directory.path().join("new").join("video.mkv");
+        assert!(!is_same_file(&output_file, &input_file));
+        Ok(())
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("-map"), "-map");
+        assert_eq!(shell_quote("0:s?"), "'0:s?'");
+        assert_eq!(shell_quote("my video.mkv"), "'my video.mkv'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0.0), "00:00:00.000");
+        assert_eq!(format_duration(3723.5), "01:02:03.500");
+    }
+
+    #[test]
+    fn test_format_date() {
+        let date = |seconds| format_date(UNIX_EPOCH + std::time::Duration::from_secs(seconds));
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(951_782_400), "2000-02-29");
+        assert_eq!(date(1_700_000_000), "2023-11-14");
+    }
+}
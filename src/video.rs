@@ -1,2 +1,9 @@
+pub mod collision;
+pub mod container;
+pub mod encoder;
 pub mod file;
 pub mod format;
+pub mod in_place;
+pub mod naming;
+pub mod stream;
+pub mod verification;
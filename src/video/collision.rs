@@ -0,0 +1,132 @@
+use std::{fmt, path::Path};
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+
+use crate::utils::{get_file_extension, get_file_stem};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum CollisionPolicy {
+    /// Fail when the output file already exists.
+    #[default]
+    Error,
+
+    /// Replace the existing output file.
+    Overwrite,
+
+    /// Leave the existing output file untouched and don't process the video.
+    Skip,
+
+    /// Number the output file with the first free -1, -2... suffix.
+    Rename,
+}
+
+impl fmt::Display for CollisionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CollisionPolicy::Error => write!(f, "error"),
+            CollisionPolicy::Overwrite => write!(f, "overwrite"),
+            CollisionPolicy::Skip => write!(f, "skip"),
+            CollisionPolicy::Rename => write!(f, "rename"),
+        }
+    }
+}
+
+impl CollisionPolicy {
+    /// Gets the output file name to use, which is None when the video must be skipped.
+    pub fn resolve<S>(&self, file_name: S) -> Result<Option<Box<str>>>
+    where
+        S: AsRef<str>,
+    {
+        let file = Path::new(file_name.as_ref());
+        if !file.exists() {
+            return Ok(Some(file_name.as_ref().into()));
+        }
+
+        match self {
+            CollisionPolicy::Error => Err(anyhow!(
+                "Output file {} path already exists.",
+                file.display()
+            )),
+            CollisionPolicy::Overwrite => Ok(Some(file_name.as_ref().into())),
+            CollisionPolicy::Skip => Ok(None),
+            CollisionPolicy::Rename => {
+                let stem = get_file_stem(file)?;
+                let extension = get_file_extension(file)?;
+                let numbered_file_name = (1..)
+                    .map(|n| file.with_file_name(format!("{}-{}.{}", stem, n, extension)))
+                    .find(|numbered_file| !numbered_file.exists())
+                    .ok_or_else(|| anyhow!("Failed to find a free name for {}.", file.display()))?;
+                numbered_file_name
+                    .to_str()
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "The file {} is not valid UTF-8. Please rename the file.",
+                            numbered_file_name.display()
+                        )
+                    })
+                    .map(|s| Some(s.into()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn get_existing_file(directory: &TempDir) -> Result<String> {
+        let file_name = directory.path().join("my_video-subs.mkv");
+        fs::write(&file_name, b"")?;
+        Ok(file_name.to_str().unwrap().to_owned())
+    }
+
+    #[test]
+    fn test_missing_file_is_used_as_is() -> Result<()> {
+        let directory = TempDir::new()?;
+        let file_name = directory.path().join("my_video-subs.mkv");
+        let file_name = file_name.to_str().unwrap();
+        for policy in [CollisionPolicy::Error, CollisionPolicy::Skip] {
+            assert_eq!(policy.resolve(file_name)?.as_deref(), Some(file_name));
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_error_when_file_exists() {
+        let directory = TempDir::new().unwrap();
+        let file_name = get_existing_file(&directory).unwrap();
+        CollisionPolicy::Error.resolve(file_name).unwrap();
+    }
+
+    #[test]
+    fn test_overwrite_and_skip_when_file_exists() -> Result<()> {
+        let directory = TempDir::new()?;
+        let file_name = get_existing_file(&directory)?;
+        assert_eq!(
+            CollisionPolicy::Overwrite.resolve(&file_name)?.as_deref(),
+            Some(file_name.as_str())
+        );
+        assert_eq!(CollisionPolicy::Skip.resolve(&file_name)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_finds_first_free_number() -> Result<()> {
+        let directory = TempDir::new()?;
+        let file_name = get_existing_file(&directory)?;
+        fs::write(directory.path().join("my_video-subs-1.mkv"), b"")?;
+
+        let renamed_file_name = CollisionPolicy::Rename.resolve(&file_name)?.unwrap();
+        assert_eq!(
+            Path::new(renamed_file_name.as_ref()),
+            directory.path().join("my_video-subs-2.mkv")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,449 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::{
+    subtitle::order::SubtitleTrack,
+    video::{
+        format::VideoFormat,
+        stream::{MediaInfo, StreamInfo, StreamKind},
+    },
+};
+
+const MP4_VIDEO_CODECS: [&str; 9] = [
+    "h264",
+    "hevc",
+    "av1",
+    "vp9",
+    "mpeg4",
+    "mpeg2video",
+    "mpeg1video",
+    "mjpeg",
+    "png",
+];
+const MP4_AUDIO_CODECS: [&str; 8] = ["aac", "mp3", "mp2", "ac3", "eac3", "opus", "flac", "alac"];
+const TEXT_SUBTITLE_CODECS: [&str; 6] = ["subrip", "ass", "ssa", "webvtt", "text", "mov_text"];
+
+/// What is done to a stream the output container can't store as it is.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum StreamAction {
+    /// The stream is encoded again with the given codec.
+    Convert { target_codec: &'static str },
+
+    /// The stream is left out of the output.
+    Drop,
+}
+
+/// A stream of the input video changed to fit in the output container.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct StreamChange {
+    pub index: usize,
+    pub kind: StreamKind,
+    pub codec: Option<Box<str>>,
+    #[serde(flatten)]
+    pub action: StreamAction,
+}
+
+/// The changes needed to copy the streams of the input video into the output container.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ContainerConversion {
+    pub changes: Vec<StreamChange>,
+
+    output_indices: Vec<usize>,
+}
+
+fn get_action(
+    stream: &StreamInfo,
+    input_format: &VideoFormat,
+    output_format: &VideoFormat,
+) -> Result<Option<StreamAction>> {
+    let Some(codec) = stream.codec.as_deref() else {
+        return Ok(None);
+    };
+    let action = match (output_format, stream.kind) {
+        (VideoFormat::MP4, StreamKind::Video) if !MP4_VIDEO_CODECS.contains(&codec) => {
+            return Err(anyhow!(
+                "The video stream {} with codec {} can't be stored in MP4 without re-encoding. Please use an MKV output or --burn-in.",
+                stream.index,
+                codec
+            ));
+        }
+        (VideoFormat::MP4, StreamKind::Audio) if !MP4_AUDIO_CODECS.contains(&codec) => {
+            Some(StreamAction::Convert {
+                target_codec: "aac",
+            })
+        }
+        (VideoFormat::MP4, StreamKind::Subtitle) if codec == "mov_text" => None,
+        (VideoFormat::MP4, StreamKind::Subtitle) if TEXT_SUBTITLE_CODECS.contains(&codec) => {
+            Some(StreamAction::Convert {
+                target_codec: "mov_text",
+            })
+        }
+        // Picture based subtitles like PGS can't be turned into text
+        (VideoFormat::MP4, StreamKind::Subtitle) => Some(StreamAction::Drop),
+        (VideoFormat::MP4, StreamKind::Attachment) => Some(StreamAction::Drop),
+        (VideoFormat::MKV, StreamKind::Subtitle) if codec == "mov_text" => {
+            Some(StreamAction::Convert {
+                target_codec: "subrip",
+            })
+        }
+        (_, StreamKind::Data | StreamKind::Unknown) if input_format != output_format => {
+            Some(StreamAction::Drop)
+        }
+        _ => None,
+    };
+    Ok(action)
+}
+
+impl ContainerConversion {
+    /// Checks every stream kept in the output against its container. When burning in, only
+    /// the first video stream is kept and it's encoded again, the other video, attachment
+    /// and data streams are dropped.
+    pub fn new(
+        media_info: &MediaInfo,
+        tracks: &[SubtitleTrack],
+        input_format: &VideoFormat,
+        output_format: &VideoFormat,
+        burn_in: bool,
+    ) -> Result<Self> {
+        let mut conversion = ContainerConversion::default();
+        let mut audio_index = 0;
+        let mut has_burned_video = false;
+        for stream in media_info.streams.iter() {
+            let output_index = match stream.kind {
+                StreamKind::Subtitle => continue,
+                StreamKind::Video if burn_in && !has_burned_video => {
+                    has_burned_video = true;
+                    continue;
+                }
+                StreamKind::Audio => {
+                    audio_index += 1;
+                    audio_index - 1
+                }
+                _ if burn_in => {
+                    conversion.drop_stream(stream);
+                    continue;
+                }
+                _ => 0,
+            };
+            conversion.push(stream, output_index, input_format, output_format)?;
+        }
+
+        // New subtitles are converted along with the rest of the subtitle tracks
+        let mut subtitle_index = 0;
+        for track in tracks.iter() {
+            match track {
+                SubtitleTrack::New(_) if burn_in => {}
+                SubtitleTrack::New(_) => subtitle_index += 1,
+                SubtitleTrack::Existing(stream) => {
+                    let action =
+                        conversion.push(stream, subtitle_index, input_format, output_format)?;
+                    if action != Some(StreamAction::Drop) {
+                        subtitle_index += 1;
+                    }
+                }
+            }
+        }
+        Ok(conversion)
+    }
+
+    fn push(
+        &mut self,
+        stream: &StreamInfo,
+        output_index: usize,
+        input_format: &VideoFormat,
+        output_format: &VideoFormat,
+    ) -> Result<Option<StreamAction>> {
+        let action = get_action(stream, input_format, output_format)?;
+        if let Some(action) = &action {
+            self.changes.push(StreamChange {
+                index: stream.index,
+                kind: stream.kind,
+                codec: stream.codec.clone(),
+                action: action.clone(),
+            });
+            self.output_indices.push(output_index);
+        }
+        Ok(action)
+    }
+
+    /// Leaves out every pre-existing subtitle stream, for outputs only keeping the new ones.
+    pub fn drop_subtitle_streams(&mut self, media_info: &MediaInfo) {
+        for stream in media_info.subtitle_streams() {
+            if self
+                .changes
+                .iter()
+                .all(|change| change.index != stream.index)
+            {
+                self.drop_stream(stream);
+            }
+        }
+    }
+
+    fn drop_stream(&mut self, stream: &StreamInfo) {
+        self.changes.push(StreamChange {
+            index: stream.index,
+            kind: stream.kind,
+            codec: stream.codec.clone(),
+            action: StreamAction::Drop,
+        });
+        self.output_indices.push(0);
+    }
+
+    fn is_dropped(&self, stream: &StreamInfo) -> bool {
+        self.changes
+            .iter()
+            .any(|change| change.index == stream.index && change.action == StreamAction::Drop)
+    }
+
+    /// The codec the stream is converted to, if it is.
+    pub fn get_target_codec(&self, stream: &StreamInfo) -> Option<&'static str> {
+        self.changes
+            .iter()
+            .find(|change| change.index == stream.index)
+            .and_then(|change| match change.action {
+                StreamAction::Convert { target_codec } => Some(target_codec),
+                StreamAction::Drop => None,
+            })
+    }
+
+    /// Leaves out the pre-existing subtitle tracks the output container can't store.
+    pub fn filter_tracks<'a>(&self, tracks: &[SubtitleTrack<'a>]) -> Vec<SubtitleTrack<'a>> {
+        tracks
+            .iter()
+            .filter(|track| match track {
+                SubtitleTrack::New(_) => true,
+                SubtitleTrack::Existing(stream) => !self.is_dropped(stream),
+            })
+            .copied()
+            .collect()
+    }
+
+    /// The arguments removing the dropped streams, other than subtitles, after mapping the
+    /// whole input video.
+    pub fn get_map_args(&self) -> Vec<String> {
+        self.changes
+            .iter()
+            .filter(|change| change.action == StreamAction::Drop)
+            .filter(|change| change.kind != StreamKind::Subtitle)
+            .flat_map(|change| ["-map".into(), format!("-0:{}", change.index)])
+            .collect()
+    }
+
+    /// The arguments encoding the converted streams, placed after the copy codecs so they
+    /// take precedence.
+    pub fn get_codec_args(&self) -> Vec<String> {
+        self.changes
+            .iter()
+            .zip(self.output_indices.iter())
+            .filter_map(|(change, output_index)| {
+                let StreamAction::Convert { target_codec } = change.action else {
+                    return None;
+                };
+                let kind = match change.kind {
+                    StreamKind::Audio => "a",
+                    StreamKind::Subtitle => "s",
+                    _ => return None,
+                };
+                Some([
+                    format!("-c:{}:{}", kind, output_index),
+                    target_codec.to_owned(),
+                ])
+            })
+            .flatten()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(index: usize, kind: StreamKind, codec: &str) -> StreamInfo {
+        StreamInfo {
+            index,
+            kind,
+            codec: Some(codec.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_mkv_to_mp4_conversion() -> Result<()> {
+        let media_info = MediaInfo {
+            streams: vec![
+                stream(0, StreamKind::Video, "h264"),
+                stream(1, StreamKind::Audio, "aac"),
+                stream(2, StreamKind::Audio, "truehd"),
+                stream(3, StreamKind::Subtitle, "hdmv_pgs_subtitle"),
+                stream(4, StreamKind::Subtitle, "ass"),
+                stream(5, StreamKind::Attachment, "ttf"),
+            ],
+            duration: None,
+        };
+        let tracks = [
+            SubtitleTrack::New(0),
+            SubtitleTrack::Existing(&media_info.streams[3]),
+            SubtitleTrack::Existing(&media_info.streams[4]),
+        ];
+        let conversion = ContainerConversion::new(
+            &media_info,
+            &tracks,
+            &VideoFormat::MKV,
+            &VideoFormat::MP4,
+            false,
+        )?;
+
+        let changed: Vec<(usize, &StreamAction)> = conversion
+            .changes
+            .iter()
+            .map(|change| (change.index, &change.action))
+            .collect();
+        let to_aac = StreamAction::Convert {
+            target_codec: "aac",
+        };
+        let to_mov_text = StreamAction::Convert {
+            target_codec: "mov_text",
+        };
+        assert_eq!(
+            changed,
+            [
+                (2, &to_aac),
+                (5, &StreamAction::Drop),
+                (3, &StreamAction::Drop),
+                (4, &to_mov_text)
+            ]
+        );
+        assert_eq!(conversion.filter_tracks(&tracks), [tracks[0], tracks[2]]);
+        assert_eq!(conversion.get_map_args(), ["-map", "-0:5"]);
+        assert_eq!(
+            conversion.get_codec_args(),
+            ["-c:a:1", "aac", "-c:s:1", "mov_text"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mp4_to_mkv_conversion() -> Result<()> {
+        let media_info = MediaInfo {
+            streams: vec![
+                stream(0, StreamKind::Video, "h264"),
+                stream(1, StreamKind::Subtitle, "mov_text"),
+                stream(2, StreamKind::Data, "bin_data"),
+            ],
+            duration: None,
+        };
+        let tracks = [SubtitleTrack::Existing(&media_info.streams[1])];
+        let conversion = ContainerConversion::new(
+            &media_info,
+            &tracks,
+            &VideoFormat::MP4,
+            &VideoFormat::MKV,
+            false,
+        )?;
+        assert_eq!(conversion.get_map_args(), ["-map", "-0:2"]);
+        assert_eq!(conversion.get_codec_args(), ["-c:s:0", "subrip"]);
+        assert_eq!(
+            conversion.get_target_codec(&media_info.streams[1]),
+            Some("subrip")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_dropped_subtitle_streams_are_reported() -> Result<()> {
+        let media_info = MediaInfo {
+            streams: vec![
+                stream(0, StreamKind::Video, "h264"),
+                stream(1, StreamKind::Subtitle, "subrip"),
+                stream(2, StreamKind::Subtitle, "hdmv_pgs_subtitle"),
+            ],
+            duration: None,
+        };
+        let mut conversion = ContainerConversion::new(
+            &media_info,
+            &[SubtitleTrack::New(0)],
+            &VideoFormat::MKV,
+            &VideoFormat::MP4,
+            false,
+        )?;
+        conversion.drop_subtitle_streams(&media_info);
+        let dropped: Vec<usize> = conversion
+            .changes
+            .iter()
+            .filter(|change| change.action == StreamAction::Drop)
+            .map(|change| change.index)
+            .collect();
+        assert_eq!(dropped, [1, 2]);
+        assert_eq!(conversion.get_map_args(), Vec::<String>::new());
+        assert_eq!(conversion.get_codec_args(), Vec::<String>::new());
+        Ok(())
+    }
+
+    #[test]
+    fn test_same_container_keeps_everything() -> Result<()> {
+        let media_info = MediaInfo {
+            streams: vec![
+                stream(0, StreamKind::Video, "vp8"),
+                stream(1, StreamKind::Audio, "vorbis"),
+                stream(2, StreamKind::Attachment, "ttf"),
+            ],
+            duration: None,
+        };
+        let conversion = ContainerConversion::new(
+            &media_info,
+            &[],
+            &VideoFormat::MKV,
+            &VideoFormat::MKV,
+            false,
+        )?;
+        assert!(conversion.changes.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_unsupported_video_codec_fails() {
+        let media_info = MediaInfo {
+            streams: vec![stream(0, StreamKind::Video, "vp8")],
+            duration: None,
+        };
+        ContainerConversion::new(
+            &media_info,
+            &[],
+            &VideoFormat::MKV,
+            &VideoFormat::MP4,
+            false,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_burn_in_encodes_the_video_again() -> Result<()> {
+        let media_info = MediaInfo {
+            streams: vec![
+                stream(0, StreamKind::Video, "vp8"),
+                stream(1, StreamKind::Audio, "vorbis"),
+                stream(2, StreamKind::Video, "mjpeg"),
+                stream(3, StreamKind::Attachment, "ttf"),
+            ],
+            duration: None,
+        };
+        let conversion = ContainerConversion::new(
+            &media_info,
+            &[SubtitleTrack::New(0)],
+            &VideoFormat::MKV,
+            &VideoFormat::MP4,
+            true,
+        )?;
+        let dropped: Vec<usize> = conversion
+            .changes
+            .iter()
+            .filter(|change| change.action == StreamAction::Drop)
+            .map(|change| change.index)
+            .collect();
+        assert_eq!(dropped, [2, 3]);
+        assert_eq!(conversion.get_codec_args(), ["-c:a:0", "aac"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+use clap::Args;
+
+/// Settings used to re-encode the video when burning in subtitles.
+#[derive(Args, Clone, Debug, PartialEq, Eq)]
+pub struct VideoEncoder {
+    /// The ffmpeg video codec used when burning in subtitles
+    #[arg(long = "video-codec", default_value = "libx264")]
+    pub codec: Box<str>,
+
+    /// The constant rate factor used when burning in subtitles, lower is better quality
+    #[arg(long, default_value_t = 23)]
+    pub crf: u8,
+
+    /// The encoder preset used when burning in subtitles, trading speed for size
+    #[arg(long, default_value = "medium")]
+    pub preset: Box<str>,
+}
+
+impl Default for VideoEncoder {
+    fn default() -> Self {
+        VideoEncoder {
+            codec: "libx264".into(),
+            crf: 23,
+            preset: "medium".into(),
+        }
+    }
+}
+
+impl VideoEncoder {
+    pub fn get_args(&self) -> Vec<String> {
+        Vec::from([
+            "-c:v".to_owned(),
+            self.codec.to_string(),
+            "-crf".to_owned(),
+            self.crf.to_string(),
+            "-preset".to_owned(),
+            self.preset.to_string(),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoder_args() {
+        let encoder = VideoEncoder {
+            codec: "libx265".into(),
+            crf: 28,
+            preset: "slow".into(),
+        };
+        assert_eq!(
+            encoder.get_args(),
+            ["-c:v", "libx265", "-crf", "28", "-preset", "slow"].map(String::from)
+        );
+    }
+}
@@ -2,12 +2,13 @@ use std::path::Path;
 
 use anyhow::{anyhow, Result};
 
-use super::format::VideoFormat;
+use super::{collision::CollisionPolicy, format::VideoFormat};
 
 #[derive(Default)]
 pub struct VideoFileBuilder {
     file_name: Option<Box<str>>,
     format: Option<VideoFormat>,
+    overwrite_existing: bool,
 }
 
 impl VideoFileBuilder {
@@ -38,12 +39,14 @@ impl VideoFileBuilder {
         })
     }
 
-    pub fn with_output_file<S>(self, file_name: S) -> Result<Self>
+    pub fn with_output_file<S>(self, file_name: S, policy: CollisionPolicy) -> Result<Self>
     where
         S: AsRef<str>,
     {
-        self.with_file(file_name, |file| {
-            if file.exists() {
+        let overwrite_existing =
+            policy == CollisionPolicy::Overwrite && Path::new(file_name.as_ref()).is_file();
+        let builder = self.with_file(file_name, |file| {
+            if file.exists() && !overwrite_existing {
                 return Err(anyhow!(
                     "Output file {} path already exists.",
                     file.display()
@@ -56,6 +59,10 @@ impl VideoFileBuilder {
                 ));
             }
             Ok(())
+        })?;
+        Ok(VideoFileBuilder {
+            overwrite_existing,
+            ..builder
         })
     }
 
@@ -67,7 +74,7 @@ impl VideoFileBuilder {
         let file = Path::new(file_name.as_ref());
 
         // Check if the file exists and is a file
-        file_validator(&file)?;
+        file_validator(file)?;
 
         let format = VideoFormat::new(file)?;
         Ok(VideoFileBuilder {
@@ -86,8 +93,9 @@ impl VideoFileBuilder {
             .ok_or_else(|| anyhow!("The file format is not defined."))?;
 
         Ok(VideoFile {
-            file_name: file_name.into(),
+            file_name,
             format,
+            overwrite_existing: self.overwrite_existing,
         })
     }
 }
@@ -96,6 +104,9 @@ impl VideoFileBuilder {
 pub struct VideoFile {
     pub file_name: Box<str>,
     pub format: VideoFormat,
+
+    /// Whether ffmpeg is allowed to replace the existing file.
+    pub overwrite_existing: bool,
 }
 
 impl VideoFile {
@@ -128,7 +139,8 @@ mod tests {
             video_file,
             VideoFile {
                 file_name: video_file_name.into(),
-                format: VideoFormat::MP4
+                format: VideoFormat::MP4,
+                overwrite_existing: false,
             }
         );
         Ok(())
@@ -148,7 +160,8 @@ mod tests {
             video_file,
             VideoFile {
                 file_name: video_file_name.into(),
-                format: VideoFormat::MKV
+                format: VideoFormat::MKV,
+                overwrite_existing: false,
             }
         );
         Ok(())
@@ -172,11 +185,35 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    pub fn test_video_builder_overwrite_existing_output() -> Result<()> {
+        let video = Builder::new().prefix("my_file").suffix(".mkv").tempfile()?;
+        let video_file = VideoFileBuilder::new()
+            .with_output_file(video.path().to_str().unwrap(), CollisionPolicy::Overwrite)?
+            .build()?;
+        assert!(video_file.overwrite_existing);
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn test_video_builder_existing_output() {
+        let video = Builder::new()
+            .prefix("my_file")
+            .suffix(".mkv")
+            .tempfile()
+            .unwrap();
+        VideoFileBuilder::new()
+            .with_output_file(video.path().to_str().unwrap(), CollisionPolicy::Error)
+            .unwrap();
+    }
+
     #[test]
     pub fn test_mp4_not_supports_multiple_subtitle_streams() {
         let video_file = VideoFile {
             file_name: "test".into(),
             format: VideoFormat::MP4,
+            overwrite_existing: false,
         };
         assert!(!video_file.supports_multiple_subtitle_streams());
     }
@@ -186,6 +223,7 @@ mod tests {
         let video_file = VideoFile {
             file_name: "test".into(),
             format: VideoFormat::MKV,
+            overwrite_existing: false,
         };
         assert!(video_file.supports_multiple_subtitle_streams());
     }
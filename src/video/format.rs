@@ -4,6 +4,7 @@ use anyhow::{anyhow, Result};
 
 use crate::utils::get_file_extension;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Eq)]
 pub enum VideoFormat {
     MP4,
@@ -30,7 +31,7 @@ mod tests {
     #[test]
     fn test_build_video_format_for_mp4() -> Result<()> {
         let file = Path::new("my_file.mp4");
-        assert_eq!(VideoFormat::new(&file)?, VideoFormat::MP4);
+        assert_eq!(VideoFormat::new(file)?, VideoFormat::MP4);
         Ok(())
     }
 
@@ -0,0 +1,193 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Result};
+
+use super::file::VideoFile;
+use crate::utils::{get_file_extension, get_file_stem};
+
+/// A temporary output written next to the input video that replaces it once verified.
+pub struct InPlaceOutput {
+    original_file_name: Box<str>,
+    temporary_file_name: Box<str>,
+    backup_file_name: Option<Box<str>>,
+    is_replaced: bool,
+}
+
+fn with_file_name(file: &Path, file_name: String) -> Result<Box<str>> {
+    file.with_file_name(file_name)
+        .to_str()
+        .ok_or_else(|| {
+            anyhow!(
+                "The file {} is not valid UTF-8. Please rename the file.",
+                file.display()
+            )
+        })
+        .map(|s| s.into())
+}
+
+impl InPlaceOutput {
+    pub fn new(video_file: &VideoFile, keep_backup: bool) -> Result<Self> {
+        let file = Path::new(video_file.get_file_name());
+        let stem = get_file_stem(file)?;
+        let extension = get_file_extension(file)?;
+
+        // Same directory so the final rename doesn't cross file systems
+        let temporary_file_name = with_file_name(
+            file,
+            format!(".{}.subtrack-{}.{}", stem, std::process::id(), extension),
+        )?;
+
+        let backup_file_name = if keep_backup {
+            let backup_file_name = format!("{}.bak", video_file.get_file_name());
+            if Path::new(&backup_file_name).exists() {
+                return Err(anyhow!(
+                    "Backup file {} already exists. Please move it before modifying the video.",
+                    backup_file_name
+                ));
+            }
+            Some(backup_file_name.into())
+        } else {
+            None
+        };
+
+        Ok(InPlaceOutput {
+            original_file_name: video_file.get_file_name().into(),
+            temporary_file_name,
+            backup_file_name,
+            is_replaced: false,
+        })
+    }
+
+    pub fn get_temporary_file_name(&self) -> &str {
+        self.temporary_file_name.as_ref()
+    }
+
+    pub fn get_original_file_name(&self) -> &str {
+        self.original_file_name.as_ref()
+    }
+
+    /// Atomically renames the temporary output over the original file,
+    /// returning the name of the backup if one was kept.
+    pub fn replace(mut self) -> Result<Option<Box<str>>> {
+        if let Some(backup_file_name) = &self.backup_file_name {
+            // Hard links keep the backup free, copy when the file system doesn't support them
+            if fs::hard_link(self.get_original_file_name(), backup_file_name.as_ref()).is_err() {
+                fs::copy(self.get_original_file_name(), backup_file_name.as_ref())?;
+            }
+        }
+        // The new file takes the place of the original, so it keeps who can read it
+        let permissions = fs::metadata(self.get_original_file_name())?.permissions();
+        fs::set_permissions(self.get_temporary_file_name(), permissions)?;
+        fs::rename(
+            self.get_temporary_file_name(),
+            self.get_original_file_name(),
+        )?;
+        self.is_replaced = true;
+        Ok(self.backup_file_name.clone())
+    }
+}
+
+impl Drop for InPlaceOutput {
+    fn drop(&mut self) {
+        let temporary_file = Path::new(self.temporary_file_name.as_ref());
+        if self.is_replaced || !temporary_file.exists() {
+            return;
+        }
+        // A leftover hidden file is better than panicking while unwinding from a failure
+        let _ = std::fs::remove_file(temporary_file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::video::format::VideoFormat;
+
+    fn get_video_file(directory: &TempDir) -> Result<VideoFile> {
+        let file_name = directory.path().join("my_video.mkv");
+        fs::write(&file_name, b"original")?;
+        Ok(VideoFile {
+            file_name: file_name.to_str().unwrap().into(),
+            format: VideoFormat::MKV,
+            overwrite_existing: false,
+        })
+    }
+
+    #[test]
+    fn test_temporary_file_is_next_to_the_original() -> Result<()> {
+        let directory = TempDir::new()?;
+        let video_file = get_video_file(&directory)?;
+        let output = InPlaceOutput::new(&video_file, false)?;
+
+        let temporary_file = Path::new(output.get_temporary_file_name());
+        assert_eq!(temporary_file.parent(), Some(directory.path()));
+        assert_eq!(VideoFormat::new(temporary_file)?, VideoFormat::MKV);
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_keeps_backup() -> Result<()> {
+        let directory = TempDir::new()?;
+        let video_file = get_video_file(&directory)?;
+        let output = InPlaceOutput::new(&video_file, true)?;
+        fs::File::create(output.get_temporary_file_name())?.write_all(b"modified")?;
+
+        let backup_file_name = output.replace()?.unwrap();
+        assert_eq!(fs::read(video_file.get_file_name())?, b"modified");
+        assert_eq!(fs::read(backup_file_name.as_ref())?, b"original");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_replace_keeps_permissions() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = TempDir::new()?;
+        let video_file = get_video_file(&directory)?;
+        fs::set_permissions(
+            video_file.get_file_name(),
+            fs::Permissions::from_mode(0o640),
+        )?;
+        let output = InPlaceOutput::new(&video_file, false)?;
+        fs::write(output.get_temporary_file_name(), b"modified")?;
+        fs::set_permissions(
+            output.get_temporary_file_name(),
+            fs::Permissions::from_mode(0o600),
+        )?;
+
+        output.replace()?;
+        let mode = fs::metadata(video_file.get_file_name())?
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o640);
+        Ok(())
+    }
+
+    #[test]
+    fn test_temporary_file_is_removed_when_not_replaced() -> Result<()> {
+        let directory = TempDir::new()?;
+        let video_file = get_video_file(&directory)?;
+        let output = InPlaceOutput::new(&video_file, false)?;
+        let temporary_file_name = output.get_temporary_file_name().to_owned();
+        fs::write(&temporary_file_name, b"broken")?;
+
+        drop(output);
+        assert!(!Path::new(&temporary_file_name).exists());
+        assert_eq!(fs::read(video_file.get_file_name())?, b"original");
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_existing_backup_is_not_overwritten() {
+        let directory = TempDir::new().unwrap();
+        let video_file = get_video_file(&directory).unwrap();
+        fs::write(directory.path().join("my_video.mkv.bak"), b"backup").unwrap();
+        InPlaceOutput::new(&video_file, true).unwrap();
+    }
+}
@@ -0,0 +1,238 @@
+use std::{
+    path::{self, Path},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Error, Result};
+
+use crate::{
+    subtitle::language::Language,
+    utils::{format_date, get_file_extension, get_file_stem},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TemplatePart {
+    Text(Box<str>),
+    Stem,
+    Extension,
+    Languages,
+    Directory,
+    Date,
+}
+
+/// The name of the output video built from the input one, like `out/{stem}.{langs}.mkv`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutputTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+impl OutputTemplate {
+    /// The template adding a suffix to the name of the input, like `{stem}-subs.{ext}`.
+    pub fn from_suffix<S>(suffix: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        OutputTemplate {
+            parts: vec![
+                TemplatePart::Stem,
+                TemplatePart::Text(format!("{}.", suffix.as_ref()).into()),
+                TemplatePart::Extension,
+            ],
+        }
+    }
+
+    /// Builds the output file name of the input. A relative name is placed in the output
+    /// directory, which defaults to the directory of the input.
+    pub fn render<I, D>(
+        &self,
+        input_file: I,
+        output_directory: Option<D>,
+        languages: &[Language],
+    ) -> Result<Box<str>>
+    where
+        I: AsRef<str>,
+        D: AsRef<str>,
+    {
+        self.render_at(input_file, output_directory, languages, SystemTime::now())
+    }
+
+    fn render_at<I, D>(
+        &self,
+        input_file: I,
+        output_directory: Option<D>,
+        languages: &[Language],
+        time: SystemTime,
+    ) -> Result<Box<str>>
+    where
+        I: AsRef<str>,
+        D: AsRef<str>,
+    {
+        let input_path = Path::new(input_file.as_ref());
+        let input_directory = input_path.parent().unwrap_or(Path::new(""));
+        let mut file_name = String::new();
+        for part in self.parts.iter() {
+            match part {
+                TemplatePart::Text(text) => file_name.push_str(text),
+                TemplatePart::Stem => file_name.push_str(&get_file_stem(input_path)?),
+                TemplatePart::Extension => file_name.push_str(&get_file_extension(input_path)?),
+                TemplatePart::Languages => file_name.push_str(&format_languages(languages)),
+                TemplatePart::Date => file_name.push_str(&format_date(time)),
+                TemplatePart::Directory => {
+                    // Absolute so the result isn't placed again in the output directory
+                    let directory = match input_directory.as_os_str().is_empty() {
+                        true => path::absolute(".")?,
+                        false => path::absolute(input_directory)?,
+                    };
+                    file_name.push_str(&directory.to_string_lossy());
+                }
+            }
+        }
+
+        let output_directory = output_directory
+            .as_ref()
+            .map_or(input_directory, |directory| Path::new(directory.as_ref()));
+        let output_path = output_directory.join(file_name);
+        output_path.to_str().map(|s| s.into()).ok_or_else(|| {
+            anyhow!(
+                "The file {} is not valid UTF-8. Please rename the file.",
+                output_path.display()
+            )
+        })
+    }
+}
+
+fn format_languages(languages: &[Language]) -> String {
+    let mut tags: Vec<&str> = Vec::with_capacity(languages.len());
+    for language in languages.iter() {
+        let tag = language.to_short_tag();
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    if tags.is_empty() {
+        return "und".to_owned();
+    }
+    tags.join("+")
+}
+
+impl FromStr for OutputTemplate {
+    type Err = Error;
+
+    fn from_str(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let (name, rest) = chars.as_str().split_once('}').ok_or_else(|| {
+                        anyhow!("The output template {} has an unclosed {{.", template)
+                    })?;
+                    let part = match name {
+                        "stem" => TemplatePart::Stem,
+                        "ext" => TemplatePart::Extension,
+                        "langs" => TemplatePart::Languages,
+                        "dir" => TemplatePart::Directory,
+                        "date" => TemplatePart::Date,
+                        _ => {
+                            return Err(anyhow!(
+                                "Unknown placeholder {{{}}} in the output template. Use {{stem}}, {{ext}}, {{langs}}, {{dir}} or {{date}}.",
+                                name
+                            ))
+                        }
+                    };
+                    if !text.is_empty() {
+                        parts.push(TemplatePart::Text(text.as_str().into()));
+                        text.clear();
+                    }
+                    parts.push(part);
+                    chars = rest.chars();
+                }
+                '}' => {
+                    return Err(anyhow!(
+                        "The output template {} has an unmatched }}. Use }}}} for a literal one.",
+                        template
+                    ))
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(TemplatePart::Text(text.into()));
+        }
+        if parts.is_empty() {
+            return Err(anyhow!("The output template can't be empty."));
+        }
+        Ok(OutputTemplate { parts })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    #[test]
+    fn test_suffix_template_matches_the_default_name() -> Result<()> {
+        let template = OutputTemplate::from_suffix("-subs");
+        assert_eq!(
+            template
+                .render("videos/movie.mkv", None::<&str>, &[])?
+                .as_ref(),
+            "videos/movie-subs.mkv"
+        );
+        assert_eq!(template, "{stem}-subs.{ext}".parse()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_template_placeholders() -> Result<()> {
+        let template: OutputTemplate = "out/{stem}.{langs}.{date}.mkv".parse()?;
+        let languages = [Language::English, Language::Spanish, Language::English];
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(
+            template
+                .render_at("videos/movie.mp4", None::<&str>, &languages, time)?
+                .as_ref(),
+            "videos/out/movie.en+es.2023-11-14.mkv"
+        );
+        assert_eq!(
+            template
+                .render_at("videos/movie.mp4", Some("/library"), &[], time)?
+                .as_ref(),
+            "/library/out/movie.und.2023-11-14.mkv"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_directory_placeholder_is_absolute() -> Result<()> {
+        let template: OutputTemplate = "{dir}/done/{stem}.{ext}".parse()?;
+        let output_file = template.render("/inbox/movie.mkv", Some("/library"), &[])?;
+        assert_eq!(output_file.as_ref(), "/inbox/done/movie.mkv");
+        Ok(())
+    }
+
+    #[test]
+    fn test_template_escapes_and_errors() -> Result<()> {
+        let template: OutputTemplate = "{{{stem}}}.{ext}".parse()?;
+        assert_eq!(
+            template.render("movie.mkv", None::<&str>, &[])?.as_ref(),
+            "{movie}.mkv"
+        );
+        assert!("{title}.mkv".parse::<OutputTemplate>().is_err());
+        assert!("{stem.mkv".parse::<OutputTemplate>().is_err());
+        assert!("stem}.mkv".parse::<OutputTemplate>().is_err());
+        Ok(())
+    }
+}
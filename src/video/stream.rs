@@ -0,0 +1,93 @@
+use std::fmt;
+
+use serde::Serialize;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamKind {
+    Video,
+    Audio,
+    Subtitle,
+    Data,
+    Attachment,
+    #[default]
+    Unknown,
+}
+
+impl StreamKind {
+    pub fn new<S>(codec_type: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        match codec_type.as_ref() {
+            "video" => StreamKind::Video,
+            "audio" => StreamKind::Audio,
+            "subtitle" => StreamKind::Subtitle,
+            "data" => StreamKind::Data,
+            "attachment" => StreamKind::Attachment,
+            _ => StreamKind::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for StreamKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamKind::Video => write!(f, "video"),
+            StreamKind::Audio => write!(f, "audio"),
+            StreamKind::Subtitle => write!(f, "subtitle"),
+            StreamKind::Data => write!(f, "data"),
+            StreamKind::Attachment => write!(f, "attachment"),
+            StreamKind::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct StreamInfo {
+    /// Absolute index of the stream inside the container.
+    pub index: usize,
+    pub kind: StreamKind,
+    pub codec: Option<Box<str>>,
+    pub language: Option<Box<str>>,
+    pub title: Option<Box<str>>,
+    pub default: bool,
+    pub forced: bool,
+    pub hearing_impaired: bool,
+    /// Duration of the stream in seconds, if known.
+    pub duration: Option<f64>,
+    /// Number of packets, only counted when verifying an output as it reads the whole file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packets: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct MediaInfo {
+    pub streams: Vec<StreamInfo>,
+    /// Duration of the whole container in seconds, if known.
+    pub duration: Option<f64>,
+}
+
+impl MediaInfo {
+    pub fn subtitle_streams(&self) -> impl Iterator<Item = &StreamInfo> {
+        self.streams
+            .iter()
+            .filter(|stream| stream.kind == StreamKind::Subtitle)
+    }
+
+    pub fn get_stream(&self, index: usize) -> Option<&StreamInfo> {
+        self.streams.iter().find(|stream| stream.index == index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_kind_from_codec_type() {
+        assert_eq!(StreamKind::new("video"), StreamKind::Video);
+        assert_eq!(StreamKind::new("subtitle"), StreamKind::Subtitle);
+        assert_eq!(StreamKind::new("something"), StreamKind::Unknown);
+    }
+}
@@ -0,0 +1,269 @@
+use anyhow::{anyhow, Result};
+
+use crate::{
+    subtitle::{
+        disposition::Disposition, file::SubtitleFile, format::SubtitleFormat,
+        language::is_same_language, order::SubtitleTrack,
+    },
+    video::{
+        container::ContainerConversion,
+        format::VideoFormat,
+        stream::{MediaInfo, StreamInfo},
+    },
+};
+
+/// A subtitle track the output video must have.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExpectedSubtitle {
+    /// The language tag of the track, not checked when unknown.
+    pub language: Option<Box<str>>,
+
+    /// The codec of the track, not checked when unknown.
+    pub codec: Option<Box<str>>,
+
+    pub disposition: Disposition,
+
+    /// How many cues the subtitle file has, not checked when unknown.
+    pub cues: Option<usize>,
+}
+
+/// What the output video must contain once ffmpeg finished.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OutputExpectation {
+    /// The subtitle tracks of the output in their order.
+    pub subtitles: Vec<ExpectedSubtitle>,
+
+    /// The duration of the input video in seconds, if known.
+    pub duration: Option<f64>,
+}
+
+impl OutputExpectation {
+    /// Describes the output of muxing the tracks. Burned subtitles become part of the video
+    /// frames, so only the pre-existing tracks are expected then.
+    pub fn new(
+        subtitles: &[SubtitleFile],
+        tracks: &[SubtitleTrack],
+        conversion: &ContainerConversion,
+        output_format: &VideoFormat,
+        input_info: &MediaInfo,
+        burn_in: bool,
+    ) -> Result<Self> {
+        let has_new_default = subtitles.iter().any(|sub| sub.disposition.default);
+        let mut expected_subtitles = Vec::with_capacity(tracks.len());
+        for track in tracks.iter() {
+            let expected_subtitle = match track {
+                SubtitleTrack::New(_) if burn_in => continue,
+                SubtitleTrack::New(i) => {
+                    let sub = &subtitles[*i];
+                    let codec = match (output_format, &sub.format) {
+                        (VideoFormat::MP4, _) => "mov_text",
+                        (VideoFormat::MKV, SubtitleFormat::Srt) => "subrip",
+                        (VideoFormat::MKV, SubtitleFormat::Ass | SubtitleFormat::Ssa) => "ass",
+                    };
+                    ExpectedSubtitle {
+                        language: sub.language.as_ref().map(|l| l.to_metadata_tag()),
+                        codec: Some(codec.into()),
+                        disposition: sub.disposition,
+                        cues: Some(sub.count_cues()?),
+                    }
+                }
+                SubtitleTrack::Existing(stream) => ExpectedSubtitle {
+                    language: stream.language.clone(),
+                    codec: conversion
+                        .get_target_codec(stream)
+                        .map(|codec| codec.into())
+                        .or_else(|| stream.codec.clone()),
+                    disposition: Disposition {
+                        // The new default track clears the flag from the existing ones
+                        default: stream.default && !has_new_default,
+                        forced: stream.forced,
+                        hearing_impaired: stream.hearing_impaired,
+                    },
+                    cues: None,
+                },
+            };
+            expected_subtitles.push(expected_subtitle);
+        }
+        Ok(OutputExpectation {
+            subtitles: expected_subtitles,
+            duration: input_info.duration,
+        })
+    }
+
+    /// Checks the probed output has every expected subtitle track and the whole video.
+    pub fn verify(&self, output_info: &MediaInfo) -> Result<()> {
+        let output_subtitles: Vec<&StreamInfo> = output_info.subtitle_streams().collect();
+        if output_subtitles.len() != self.subtitles.len() {
+            return Err(anyhow!(
+                "The output video has {} subtitle streams instead of {}.",
+                output_subtitles.len(),
+                self.subtitles.len()
+            ));
+        }
+
+        let has_default = self.subtitles.iter().any(|sub| sub.disposition.default);
+        for (stream, expected) in output_subtitles.iter().zip(self.subtitles.iter()) {
+            verify_subtitle(stream, expected, has_default)?;
+        }
+
+        // Streams cut short by ffmpeg make the output shorter than the input
+        if let (Some(duration), Some(output_duration)) = (self.duration, output_info.duration) {
+            if (duration - output_duration).abs() > f64::max(1.0, duration * 0.01) {
+                return Err(anyhow!(
+                    "The output video lasts {:.1} seconds instead of {:.1}.",
+                    output_duration,
+                    duration
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn verify_subtitle(
+    stream: &StreamInfo,
+    expected: &ExpectedSubtitle,
+    has_default: bool,
+) -> Result<()> {
+    if let Some(language) = &expected.language {
+        let output_language = stream.language.as_deref().unwrap_or("und");
+        if !is_same_language(output_language, language) {
+            return Err(anyhow!(
+                "The subtitle stream {} of the output video has the language {} instead of {}.",
+                stream.index,
+                output_language,
+                language
+            ));
+        }
+    }
+    if let (Some(codec), Some(output_codec)) = (&expected.codec, &stream.codec) {
+        if codec != output_codec {
+            return Err(anyhow!(
+                "The subtitle stream {} of the output video has the codec {} instead of {}.",
+                stream.index,
+                output_codec,
+                codec
+            ));
+        }
+    }
+
+    // Without an explicit default track the container may pick one on its own
+    let disposition = &expected.disposition;
+    let is_default_wrong = has_default && stream.default != disposition.default;
+    if is_default_wrong
+        || stream.forced != disposition.forced
+        || stream.hearing_impaired != disposition.hearing_impaired
+    {
+        return Err(anyhow!(
+            "The subtitle stream {} of the output video doesn't have the {} disposition.",
+            stream.index,
+            disposition.to_ffmpeg_value()
+        ));
+    }
+
+    // Some containers add empty cues to clear the screen between them
+    if let (Some(cues), Some(packets)) = (expected.cues, stream.packets) {
+        if packets < cues as u64 {
+            return Err(anyhow!(
+                "The subtitle stream {} of the output video has {} cues instead of {}.",
+                stream.index,
+                packets,
+                cues
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video::stream::StreamKind;
+
+    fn output_subtitle(index: usize, language: &str, packets: u64) -> StreamInfo {
+        StreamInfo {
+            index,
+            kind: StreamKind::Subtitle,
+            codec: Some("subrip".into()),
+            language: Some(language.into()),
+            packets: Some(packets),
+            ..Default::default()
+        }
+    }
+
+    fn expected_subtitle(language: &str, cues: usize) -> ExpectedSubtitle {
+        ExpectedSubtitle {
+            language: Some(language.into()),
+            codec: Some("subrip".into()),
+            disposition: Disposition::default(),
+            cues: Some(cues),
+        }
+    }
+
+    fn get_expectation() -> OutputExpectation {
+        OutputExpectation {
+            subtitles: vec![expected_subtitle("eng", 10), expected_subtitle("fre", 5)],
+            duration: Some(600.0),
+        }
+    }
+
+    fn get_output_info() -> MediaInfo {
+        MediaInfo {
+            streams: vec![output_subtitle(2, "eng", 10), output_subtitle(3, "fra", 5)],
+            duration: Some(600.4),
+        }
+    }
+
+    #[test]
+    fn test_complete_output_is_verified() -> Result<()> {
+        get_expectation().verify(&get_output_info())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_missing_subtitle_stream_fails() {
+        let mut output_info = get_output_info();
+        output_info.streams.pop();
+        get_expectation().verify(&output_info).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_wrong_language_fails() {
+        let mut output_info = get_output_info();
+        output_info.streams[1].language = Some("spa".into());
+        get_expectation().verify(&output_info).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_lost_cues_fail() {
+        let mut output_info = get_output_info();
+        output_info.streams[0].packets = Some(7);
+        get_expectation().verify(&output_info).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_shorter_output_fails() {
+        let mut output_info = get_output_info();
+        output_info.duration = Some(300.0);
+        get_expectation().verify(&output_info).unwrap();
+    }
+
+    #[test]
+    fn test_default_disposition() -> Result<()> {
+        let mut expectation = get_expectation();
+        let mut output_info = get_output_info();
+
+        // The container may choose a default track when none was asked
+        output_info.streams[0].default = true;
+        expectation.verify(&output_info)?;
+
+        expectation.subtitles[1].disposition.default = true;
+        assert!(expectation.verify(&output_info).is_err());
+        output_info.streams[0].default = false;
+        output_info.streams[1].default = true;
+        expectation.verify(&output_info)
+    }
+}
@@ -0,0 +1,612 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, iter,
+    path::{self, Path, PathBuf},
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    batch::{BatchJob, BatchStatus},
+    subtitle::language::Language,
+    utils::{get_file_extension, get_file_stem},
+    video::naming::OutputTemplate,
+};
+
+/// The size and modification time of a file, used to notice when it changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileSignature {
+    pub size: u64,
+    pub modified: u64,
+}
+
+impl FileSignature {
+    pub fn new<S>(file_name: S) -> Result<Self>
+    where
+        S: AsRef<str>,
+    {
+        let metadata = fs::metadata(file_name.as_ref())?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Ok(FileSignature {
+            size: metadata.len(),
+            modified: modified.as_secs(),
+        })
+    }
+}
+
+/// The signatures of a video and of its subtitles, so a subtitle arriving later makes the
+/// video be processed again.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobSignature {
+    #[serde(rename = "signature")]
+    pub video: FileSignature,
+
+    #[serde(default)]
+    pub subtitles: BTreeMap<Box<str>, FileSignature>,
+}
+
+impl JobSignature {
+    pub fn new(job: &BatchJob) -> Result<Self> {
+        let mut subtitles = BTreeMap::new();
+        for subtitle in job.subtitles.iter() {
+            subtitles.insert(
+                subtitle.file_name.clone(),
+                FileSignature::new(&subtitle.file_name)?,
+            );
+        }
+        Ok(JobSignature {
+            video: FileSignature::new(&job.video_file)?,
+            subtitles,
+        })
+    }
+
+    fn get_files(&self) -> impl Iterator<Item = (&str, FileSignature)> {
+        self.subtitles
+            .iter()
+            .map(|(file_name, signature)| (file_name.as_ref(), *signature))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobOutcome {
+    Done,
+    Skipped,
+    Failed,
+}
+
+impl From<&BatchStatus> for JobOutcome {
+    fn from(status: &BatchStatus) -> Self {
+        match status {
+            BatchStatus::Done => JobOutcome::Done,
+            BatchStatus::Skipped(_) => JobOutcome::Skipped,
+            BatchStatus::Failed(_) => JobOutcome::Failed,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessedVideo {
+    #[serde(flatten)]
+    pub signature: JobSignature,
+    pub outcome: JobOutcome,
+
+    /// How many times in a row the job failed with these files.
+    #[serde(default)]
+    pub failures: u32,
+}
+
+/// The videos already handled by the watcher, kept in a file to survive restarts.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchState {
+    processed: BTreeMap<Box<str>, ProcessedVideo>,
+}
+
+impl WatchState {
+    pub fn load<S>(file_name: S) -> Result<Self>
+    where
+        S: AsRef<str>,
+    {
+        let file = Path::new(file_name.as_ref());
+        if !file.exists() {
+            return Ok(WatchState::default());
+        }
+        let content = fs::read_to_string(file)?;
+        serde_json::from_str(&content)
+            .map_err(|e| anyhow!("The watch state {} is not valid: {}.", file.display(), e))
+    }
+
+    /// Writes the state through a temporary file so a crash never leaves it half written.
+    pub fn save<S>(&self, file_name: S) -> Result<()>
+    where
+        S: AsRef<str>,
+    {
+        let temporary_file_name = format!("{}.tmp", file_name.as_ref());
+        fs::write(&temporary_file_name, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&temporary_file_name, file_name.as_ref())?;
+        Ok(())
+    }
+
+    /// Whether the video was handled and neither it nor its subtitles changed since. A
+    /// failed job is tried again until it failed `MAX_FAILURES` times.
+    pub fn is_processed<S>(&self, video_file: S, signature: &JobSignature) -> bool
+    where
+        S: AsRef<str>,
+    {
+        self.processed
+            .get(video_file.as_ref())
+            .is_some_and(|processed| {
+                processed.signature == *signature
+                    && (processed.outcome != JobOutcome::Failed
+                        || processed.failures >= MAX_FAILURES)
+            })
+    }
+
+    pub fn record<S>(&mut self, video_file: S, signature: JobSignature, outcome: JobOutcome)
+    where
+        S: AsRef<str>,
+    {
+        let previous_failures = self
+            .processed
+            .get(video_file.as_ref())
+            .filter(|processed| processed.signature == signature)
+            .map_or(0, |processed| processed.failures);
+        let failures = match outcome {
+            JobOutcome::Failed => previous_failures + 1,
+            _ => 0,
+        };
+        self.processed.insert(
+            video_file.as_ref().into(),
+            ProcessedVideo {
+                signature,
+                outcome,
+                failures,
+            },
+        );
+    }
+}
+
+/// How many times a job is tried before waiting for its files to change.
+pub const MAX_FAILURES: u32 = 3;
+
+/// What the watcher remembers between two scans of the directory.
+pub struct WatchSession {
+    state: WatchState,
+
+    state_file: Option<Box<str>>,
+    tracker: StabilityTracker,
+
+    watched_path: PathBuf,
+
+    output_path: PathBuf,
+    has_retries: bool,
+}
+
+fn resolve_path(file: &Path) -> Result<PathBuf> {
+    let file = path::absolute(file)?;
+    for ancestor in file.ancestors() {
+        if let Ok(resolved_ancestor) = fs::canonicalize(ancestor) {
+            return Ok(resolved_ancestor.join(file.strip_prefix(ancestor)?));
+        }
+    }
+    Ok(file)
+}
+
+impl WatchSession {
+    pub fn new(
+        state: WatchState,
+        state_file: Option<Box<str>>,
+        settle_time: Duration,
+        watched_path: PathBuf,
+        output_path: PathBuf,
+    ) -> Self {
+        WatchSession {
+            state,
+            state_file,
+            tracker: StabilityTracker::new(settle_time),
+            watched_path,
+            output_path,
+            has_retries: false,
+        }
+    }
+
+    /// Picks the planned jobs to run: the ones with subtitles, outside of the output
+    /// directory, not handled since their files changed and whose files all settled.
+    pub fn get_ready_jobs(
+        &mut self,
+        jobs: Vec<BatchJob>,
+        now: Instant,
+    ) -> Vec<(BatchJob, JobSignature)> {
+        self.has_retries = false;
+        let mut ready_jobs = Vec::new();
+        for job in jobs.into_iter() {
+            let is_result = fs::canonicalize(job.video_file.as_ref())
+                .is_ok_and(|video_path| video_path.starts_with(&self.output_path));
+            if (job.subtitles.is_empty() && job.error.is_none()) || is_result {
+                continue;
+            }
+            // The files may be removed while planning
+            let Ok(signature) = JobSignature::new(&job) else {
+                continue;
+            };
+            if self.state.is_processed(&job.video_file, &signature) {
+                continue;
+            }
+
+            // Wait until the video and every subtitle stopped changing
+            let files: Vec<(&str, FileSignature)> =
+                iter::once((job.video_file.as_ref(), signature.video))
+                    .chain(signature.get_files())
+                    .collect();
+            let unstable_files = files
+                .iter()
+                .filter(|(file_name, file_signature)| {
+                    !self.tracker.is_stable(file_name, *file_signature, now)
+                })
+                .count();
+            if unstable_files > 0 {
+                continue;
+            }
+            for (file_name, _) in files.iter() {
+                self.tracker.forget(file_name);
+            }
+            ready_jobs.push((job, signature));
+        }
+        ready_jobs
+    }
+
+    /// Fails for a result placed in the watched directory, like with a `{dir}` template, as
+    /// it would be picked up as a new video. Only the output directory is left out of the scan.
+    pub fn check_result_file<S>(&self, result_file_name: S) -> Result<()>
+    where
+        S: AsRef<str>,
+    {
+        let result_path = resolve_path(Path::new(result_file_name.as_ref()))?;
+        if result_path.starts_with(&self.watched_path)
+            && !result_path.starts_with(&self.output_path)
+        {
+            return Err(anyhow!(
+                "The result {} would be placed in the watched directory and processed again. Please change the output template.",
+                result_file_name.as_ref()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Remembers how the job ended and saves the state.
+    pub fn finish_job<S>(
+        &mut self,
+        video_file: S,
+        signature: JobSignature,
+        outcome: JobOutcome,
+    ) -> Result<()>
+    where
+        S: AsRef<str>,
+    {
+        self.state.record(&video_file, signature.clone(), outcome);
+        if !self.state.is_processed(&video_file, &signature) {
+            self.has_retries = true;
+        }
+        match &self.state_file {
+            Some(state_file) => self.state.save(state_file),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the directory must be scanned again without a change, as some files are
+    /// settling or some jobs failed and are tried again.
+    pub fn is_waiting(&self) -> bool {
+        self.has_retries || self.tracker.is_waiting()
+    }
+}
+
+/// Tells when files stopped growing, as downloads are written over a while.
+pub struct StabilityTracker {
+    settle_time: Duration,
+    seen: HashMap<Box<str>, (FileSignature, Instant)>,
+}
+
+impl StabilityTracker {
+    pub fn new(settle_time: Duration) -> Self {
+        StabilityTracker {
+            settle_time,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Whether the file kept the same signature for the settle time.
+    pub fn is_stable<S>(&mut self, file_name: S, signature: FileSignature, now: Instant) -> bool
+    where
+        S: AsRef<str>,
+    {
+        match self.seen.get(file_name.as_ref()) {
+            Some((seen_signature, since)) if *seen_signature == signature => {
+                now.duration_since(*since) >= self.settle_time
+            }
+            _ => {
+                self.seen
+                    .insert(file_name.as_ref().into(), (signature, now));
+                false
+            }
+        }
+    }
+
+    /// Whether some files are still being watched until they settle.
+    pub fn is_waiting(&self) -> bool {
+        !self.seen.is_empty()
+    }
+
+    pub fn forget<S>(&mut self, file_name: S)
+    where
+        S: AsRef<str>,
+    {
+        self.seen.remove(file_name.as_ref());
+    }
+}
+
+/// Gets the hidden file ffmpeg writes next to the result and the name it's moved to once
+/// complete, so other programs never pick up a partial video.
+pub fn get_result_file_names<V, O>(
+    video_file: V,
+    output_directory: O,
+    template: &OutputTemplate,
+    languages: &[Language],
+) -> Result<(Box<str>, Box<str>)>
+where
+    V: AsRef<str>,
+    O: AsRef<str>,
+{
+    let result_file_name = template.render(&video_file, Some(output_directory), languages)?;
+    let result_file = Path::new(result_file_name.as_ref());
+    let stem = get_file_stem(Path::new(video_file.as_ref()))?;
+    let extension = get_file_extension(result_file)?;
+    let partial_file = result_file.with_file_name(format!(".{}.partial.{}", stem, extension));
+    let partial_file_name = partial_file.to_str().ok_or_else(|| {
+        anyhow!(
+            "The file {} is not valid UTF-8. Please rename the file.",
+            partial_file.display()
+        )
+    })?;
+    Ok((partial_file_name.into(), result_file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::subtitle::{discovery::DiscoveredSubtitle, disposition::Disposition};
+
+    fn get_signature(size: u64) -> JobSignature {
+        JobSignature {
+            video: FileSignature {
+                size,
+                modified: 1700000000,
+            },
+            subtitles: BTreeMap::from([(
+                "/inbox/video.en.srt".into(),
+                FileSignature {
+                    size: 10,
+                    modified: 1700000000,
+                },
+            )]),
+        }
+    }
+
+    fn create_job(directory: &Path) -> Result<BatchJob> {
+        let video_file = directory.join("video.mkv");
+        let subtitle_file = directory.join("video.en.srt");
+        fs::write(&video_file, b"video")?;
+        fs::write(&subtitle_file, b"1")?;
+        Ok(BatchJob {
+            video_file: video_file.to_str().unwrap().into(),
+            subtitles: vec![DiscoveredSubtitle {
+                file_name: subtitle_file.to_str().unwrap().into(),
+                language: Some(Language::English),
+                disposition: Disposition::default(),
+            }],
+            error: None,
+        })
+    }
+
+    fn get_ready_videos(session: &mut WatchSession, job: &BatchJob, now: Instant) -> usize {
+        let jobs = vec![BatchJob {
+            video_file: job.video_file.clone(),
+            subtitles: job.subtitles.clone(),
+            error: None,
+        }];
+        session.get_ready_jobs(jobs, now).len()
+    }
+
+    #[test]
+    fn test_state_survives_restarts() -> Result<()> {
+        let directory = TempDir::new()?;
+        let state_file = directory.path().join("state.json");
+        let state_file = state_file.to_str().unwrap();
+        let signature = get_signature(1024);
+
+        let mut state = WatchState::load(state_file)?;
+        assert!(!state.is_processed("/inbox/video.mkv", &signature));
+        state.record("/inbox/video.mkv", signature.clone(), JobOutcome::Done);
+        state.save(state_file)?;
+
+        let state = WatchState::load(state_file)?;
+        assert!(state.is_processed("/inbox/video.mkv", &signature));
+        assert!(!state.is_processed("/inbox/video.mkv", &get_signature(2048)));
+
+        // A new subtitle is muxed into the video already processed
+        let mut new_subtitle_signature = signature.clone();
+        new_subtitle_signature
+            .subtitles
+            .insert("/inbox/video.fr.srt".into(), signature.video);
+        assert!(!state.is_processed("/inbox/video.mkv", &new_subtitle_signature));
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_jobs_are_tried_again() {
+        let mut state = WatchState::default();
+        let signature = get_signature(1024);
+        for _ in 0..MAX_FAILURES {
+            assert!(!state.is_processed("/inbox/video.mkv", &signature));
+            state.record("/inbox/video.mkv", signature.clone(), JobOutcome::Failed);
+        }
+        assert!(state.is_processed("/inbox/video.mkv", &signature));
+
+        // Changed files get their own tries
+        let changed_signature = get_signature(2048);
+        assert!(!state.is_processed("/inbox/video.mkv", &changed_signature));
+        state.record(
+            "/inbox/video.mkv",
+            changed_signature.clone(),
+            JobOutcome::Failed,
+        );
+        assert!(!state.is_processed("/inbox/video.mkv", &changed_signature));
+        state.record(
+            "/inbox/video.mkv",
+            changed_signature.clone(),
+            JobOutcome::Done,
+        );
+        assert!(state.is_processed("/inbox/video.mkv", &changed_signature));
+    }
+
+    #[test]
+    fn test_session_runs_settled_jobs_once() -> Result<()> {
+        let directory = TempDir::new()?;
+        let output_directory = TempDir::new()?;
+        let state_file = directory.path().join(".state.json");
+        let mut session = WatchSession::new(
+            WatchState::default(),
+            Some(state_file.to_str().unwrap().into()),
+            Duration::from_secs(5),
+            fs::canonicalize(directory.path())?,
+            fs::canonicalize(output_directory.path())?,
+        );
+        let job = create_job(directory.path())?;
+        let start = Instant::now();
+
+        assert_eq!(get_ready_videos(&mut session, &job, start), 0);
+        assert!(session.is_waiting());
+        let later = start + Duration::from_secs(5);
+        assert_eq!(get_ready_videos(&mut session, &job, later), 1);
+        assert!(!session.is_waiting());
+
+        // A failed job waits for its files to settle again before another try
+        let signature = JobSignature::new(&job)?;
+        session.finish_job(&job.video_file, signature.clone(), JobOutcome::Failed)?;
+        assert!(session.is_waiting());
+        let later = later + Duration::from_secs(1);
+        assert_eq!(get_ready_videos(&mut session, &job, later), 0);
+        let later = later + Duration::from_secs(5);
+        assert_eq!(get_ready_videos(&mut session, &job, later), 1);
+
+        session.finish_job(&job.video_file, signature, JobOutcome::Done)?;
+        assert!(!session.is_waiting());
+        let later = later + Duration::from_secs(10);
+        assert_eq!(get_ready_videos(&mut session, &job, later), 0);
+        assert!(WatchState::load(state_file.to_str().unwrap())?
+            .is_processed(&job.video_file, &JobSignature::new(&job)?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_skips_results_and_videos_without_subtitles() -> Result<()> {
+        let directory = TempDir::new()?;
+        let mut session = WatchSession::new(
+            WatchState::default(),
+            None,
+            Duration::ZERO,
+            fs::canonicalize(directory.path())?,
+            fs::canonicalize(directory.path())?,
+        );
+        let job = create_job(directory.path())?;
+        let lonely_video = directory.path().join("lonely.mkv");
+        fs::write(&lonely_video, b"video")?;
+        let jobs = vec![
+            job,
+            BatchJob {
+                video_file: lonely_video.to_str().unwrap().into(),
+                subtitles: Vec::new(),
+                error: None,
+            },
+        ];
+        assert!(session.get_ready_jobs(jobs, Instant::now()).is_empty());
+        assert!(!session.is_waiting());
+        Ok(())
+    }
+
+    #[test]
+    fn test_results_in_the_watched_directory_fail() -> Result<()> {
+        let directory = TempDir::new()?;
+        let output_directory = directory.path().join("out");
+        fs::create_dir(&output_directory)?;
+        let session = WatchSession::new(
+            WatchState::default(),
+            None,
+            Duration::ZERO,
+            fs::canonicalize(directory.path())?,
+            fs::canonicalize(&output_directory)?,
+        );
+        let template: OutputTemplate = "{dir}/done/{stem}.{ext}".parse()?;
+        let video_file = directory.path().join("video.mkv");
+        let (_, result) = get_result_file_names(
+            video_file.to_str().unwrap(),
+            output_directory.to_str().unwrap(),
+            &template,
+            &[],
+        )?;
+        assert!(session.check_result_file(&result).is_err());
+
+        let template = OutputTemplate::from_suffix("-subs");
+        let (_, result) = get_result_file_names(
+            video_file.to_str().unwrap(),
+            output_directory.to_str().unwrap(),
+            &template,
+            &[],
+        )?;
+        session.check_result_file(&result)?;
+        let other_directory = TempDir::new()?;
+        session.check_result_file(other_directory.path().join("video.mkv").to_str().unwrap())
+    }
+
+    #[test]
+    fn test_file_is_stable_after_settle_time() {
+        let mut tracker = StabilityTracker::new(Duration::from_secs(5));
+        let start = Instant::now();
+        let signature = FileSignature {
+            size: 10,
+            modified: 1,
+        };
+        let grown_signature = FileSignature {
+            size: 20,
+            ..signature
+        };
+
+        assert!(!tracker.is_stable("video.mkv", signature, start));
+        assert!(!tracker.is_stable("video.mkv", grown_signature, start + Duration::from_secs(3)));
+        assert!(!tracker.is_stable("video.mkv", grown_signature, start + Duration::from_secs(7)));
+        assert!(tracker.is_stable("video.mkv", grown_signature, start + Duration::from_secs(8)));
+    }
+
+    #[test]
+    fn test_result_file_names() -> Result<()> {
+        let template = OutputTemplate::from_suffix("-subs");
+        let (partial, result) =
+            get_result_file_names("/inbox/video.mkv", "/library", &template, &[])?;
+        assert_eq!(partial.as_ref(), "/library/.video.partial.mkv");
+        assert_eq!(result.as_ref(), "/library/video-subs.mkv");
+
+        let template = "{langs}/{stem}.mkv".parse()?;
+        let languages = [Language::French];
+        let (partial, result) =
+            get_result_file_names("/inbox/video.mp4", "/library", &template, &languages)?;
+        assert_eq!(partial.as_ref(), "/library/fr/.video.partial.mkv");
+        assert_eq!(result.as_ref(), "/library/fr/video.mkv");
+        Ok(())
+    }
+}